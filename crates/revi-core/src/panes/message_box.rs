@@ -95,9 +95,9 @@ impl Pane for MessageBox {
     }
 
     fn update(&mut self, _: Mode, keys: revi_ui::Keys) {
-        let revi_ui::Keys::Key(revi_ui::Key::Null) = keys else {
+        if !keys.is_null() {
             return;
-        };
+        }
         self.closing = true;
     }
     fn cursor(&self) -> Option<Pos> {
@@ -2,7 +2,7 @@ use crate::{layout::Pos, subscription::Subscription, widget::BoxWidget};
 use crossterm::{cursor::SetCursorStyle, Result};
 pub trait App: Sized {
     type Settings: std::fmt::Debug;
-    type Message: std::fmt::Debug;
+    type Message: std::fmt::Debug + Send + 'static;
     fn new(settings: Self::Settings) -> Self;
     fn update(&mut self, message: Self::Message) -> Option<Self::Message>;
     fn view(&self) -> BoxWidget;
@@ -18,6 +18,13 @@ pub trait App: Sized {
     fn quit(&self) -> bool {
         true
     }
+    /// Mouse events are only reported while this returns `true`.
+    fn mouse_capture(&self) -> bool {
+        false
+    }
+    /// Called after `update` or `view` panicked, once the terminal has been restored.
+    /// Last chance to write anything that would otherwise be lost.
+    fn emergency_save(&mut self) {}
     fn run(&mut self) -> Result<()> {
         crate::runtime::run(self)
     }
@@ -0,0 +1,226 @@
+use crate::grid::{Cell, Grid};
+use crate::layout::{Pos, Size};
+use crossterm::{
+    cursor::{Hide, MoveTo, SetCursorStyle, Show},
+    event::{DisableMouseCapture, EnableMouseCapture},
+    style::{Attribute, ContentStyle, Print, ResetColor, SetAttribute, SetStyle},
+    terminal::{Clear, ClearType},
+    QueueableCommand, Result,
+};
+use std::io::Write;
+
+/// Where the runtime sends a rendered frame.
+pub trait Backend {
+    /// Draws `cells`, given in row major order, at their positions.
+    fn draw<'a, I>(&mut self, cells: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Pos, &'a Cell)>;
+    fn clear(&mut self) -> Result<()>;
+    fn hide_cursor(&mut self) -> Result<()>;
+    fn show_cursor(&mut self, shape: SetCursorStyle) -> Result<()>;
+    fn move_cursor(&mut self, pos: Pos) -> Result<()>;
+    fn set_mouse_capture(&mut self, on: bool) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+}
+
+/// Backend that writes to a terminal with crossterm.
+#[derive(Debug)]
+pub struct CrosstermBackend<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CrosstermBackend<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+}
+
+impl<W: Write> Backend for CrosstermBackend<W> {
+    /// Cursor moves are skipped for cells that follow each other on a row
+    /// and the style is only set when it differs from the previous cell.
+    fn draw<'a, I>(&mut self, cells: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Pos, &'a Cell)>,
+    {
+        let w = &mut self.writer;
+        let mut expected: Option<Pos> = None;
+        let mut style: Option<ContentStyle> = None;
+        for (pos, cell) in cells {
+            if expected != Some(pos) {
+                w.queue(MoveTo(pos.x, pos.y))?;
+            }
+            if style != Some(cell.style) {
+                w.queue(SetAttribute(Attribute::Reset))?;
+                w.queue(ResetColor)?;
+                w.queue(SetStyle(cell.style))?;
+                style = Some(cell.style);
+            }
+            w.queue(Print(cell.symbol))?;
+            expected = Some(Pos::new(pos.x + 1, pos.y));
+        }
+        if style.is_some() {
+            w.queue(SetAttribute(Attribute::Reset))?;
+            w.queue(ResetColor)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.writer.queue(Clear(ClearType::All))?;
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        self.writer.queue(Hide)?;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, shape: SetCursorStyle) -> Result<()> {
+        self.writer.queue(shape)?;
+        self.writer.queue(Show)?;
+        Ok(())
+    }
+
+    fn move_cursor(&mut self, pos: Pos) -> Result<()> {
+        self.writer.queue(MoveTo(pos.x, pos.y))?;
+        Ok(())
+    }
+
+    fn set_mouse_capture(&mut self, on: bool) -> Result<()> {
+        if on {
+            self.writer.queue(EnableMouseCapture)?;
+        } else {
+            self.writer.queue(DisableMouseCapture)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Backend that keeps the screen in memory, used to test what an app renders.
+#[derive(Clone)]
+pub struct TestBackend {
+    screen: Grid,
+    cursor: Pos,
+    cursor_shape: Option<SetCursorStyle>,
+    mouse_capture: bool,
+}
+
+impl TestBackend {
+    pub fn new(size: Size) -> Self {
+        Self {
+            screen: Grid::new(size),
+            cursor: Pos::default(),
+            cursor_shape: None,
+            mouse_capture: false,
+        }
+    }
+
+    #[must_use]
+    pub fn screen(&self) -> &Grid {
+        &self.screen
+    }
+
+    /// Text of every row on screen without styling.
+    #[must_use]
+    pub fn lines(&self) -> Vec<String> {
+        self.screen.lines()
+    }
+
+    #[must_use]
+    pub fn cursor(&self) -> Pos {
+        self.cursor
+    }
+
+    /// Shape of the cursor, `None` while it is hidden.
+    #[must_use]
+    pub fn cursor_shape(&self) -> Option<SetCursorStyle> {
+        self.cursor_shape
+    }
+
+    #[must_use]
+    pub fn mouse_capture(&self) -> bool {
+        self.mouse_capture
+    }
+}
+
+impl std::fmt::Debug for TestBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in self.lines() {
+            writeln!(f, "|{line}|")?;
+        }
+        Ok(())
+    }
+}
+
+impl Backend for TestBackend {
+    fn draw<'a, I>(&mut self, cells: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Pos, &'a Cell)>,
+    {
+        for (pos, cell) in cells {
+            self.screen.set(pos.x, pos.y, *cell);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.screen.clear();
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        self.cursor_shape = None;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, shape: SetCursorStyle) -> Result<()> {
+        self.cursor_shape = Some(shape);
+        Ok(())
+    }
+
+    fn move_cursor(&mut self, pos: Pos) -> Result<()> {
+        self.cursor = pos;
+        Ok(())
+    }
+
+    fn set_mouse_capture(&mut self, on: bool) -> Result<()> {
+        self.mouse_capture = on;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_backend_draws_changed_cells() {
+    let mut backend = TestBackend::new(Size::new(4, 1));
+    let prev = Grid::new(Size::new(4, 1));
+    let mut next = prev.clone();
+    next.set_str(1, 0, "hi", ContentStyle::new());
+    backend.draw(prev.diff(&next)).unwrap();
+    assert_eq!(backend.lines(), vec![" hi ".to_string()]);
+}
+
+#[test]
+fn crossterm_backend_skips_moves_for_adjacent_cells() {
+    let mut backend = CrosstermBackend::new(Vec::new());
+    let prev = Grid::new(Size::new(4, 2));
+    let mut next = prev.clone();
+    next.set_str(0, 1, "ab", ContentStyle::new());
+    backend.draw(prev.diff(&next)).unwrap();
+    let out = String::from_utf8(backend.writer_mut().clone()).unwrap();
+    assert_eq!(out.matches("\x1b[2;1H").count(), 1);
+    assert_eq!(out.matches('H').count(), 1);
+    assert!(out.contains("ab"));
+}
@@ -1,6 +1,6 @@
+use crate::grid::Grid;
 use crate::layout::{Pos, Rect, Size, Stack};
 use crate::widget::{BoxWidget, Widget};
-use std::io::Stdout;
 #[derive(Debug, Default, Clone)]
 pub struct Container {
     pub bounds: Rect,
@@ -64,7 +64,7 @@ impl Widget for Container {
         self.bounds.height()
     }
 
-    fn draw(&self, stdout: &mut Stdout, bounds: Rect) {
+    fn draw(&self, grid: &mut Grid, bounds: Rect) {
         for (widget, mut wbounds) in self.children.iter().zip(generate_layout(
             bounds,
             self.bounds,
@@ -78,7 +78,7 @@ impl Widget for Container {
                 let y_offset = height.saturating_sub(content_height); // + (idx as u16);
                 wbounds.y += y_offset * self.vert_center as u16;
             }
-            widget.draw(stdout, wbounds);
+            widget.draw(grid, wbounds);
         }
     }
     fn debug_name(&self) -> String {
@@ -86,29 +86,41 @@ impl Widget for Container {
     }
 }
 
+/// Places `children` one after the other starting at the origin of the container.
+/// `root` is the area the parent gave the container and `current` the container's own bounds.
 fn generate_layout(root: Rect, current: Rect, children: &[BoxWidget], stack: Stack) -> Vec<Rect> {
+    let origin = Pos::new(root.x() + current.x(), root.y() + current.y());
+    if let Stack::Layered = stack {
+        // Every layer gets the whole container and places itself within it.
+        let size = Size::new(
+            current.width().min(root.width()),
+            current.height().min(root.height()),
+        );
+        return vec![Rect::with_position(origin, size); children.len()];
+    }
     children.iter().fold(vec![], |mut acc, child| {
-        let last = acc.last().cloned().unwrap_or_default();
-        let x = match stack {
-            Stack::Vertically => current.x() + child.x() + root.x(),
-            Stack::Horizontally => current.x() + child.x() + last.width() + last.x() + root.x(),
-        };
-        let y = match stack {
-            Stack::Vertically => current.y() + child.y() + last.height() + last.y() + root.y(),
-            Stack::Horizontally => current.y() + child.y() + root.y(),
+        // space taken up by the children placed so far
+        let (used_x, used_y) = match (acc.last(), stack) {
+            (Some(last), Stack::Horizontally) => ((last.x() + last.width()) - origin.x, 0),
+            (Some(last), Stack::Vertically) => (0, (last.y() + last.height()) - origin.y),
+            (None, _) | (_, Stack::Layered) => (0, 0),
         };
+        let x = origin.x + used_x + child.x();
+        let y = origin.y + used_y + child.y();
         let width = match stack {
             // NOTE: child should be priority if Shrinking is in effect
-            Stack::Vertically => child.width().max(current.width()).min(root.width()),
+            Stack::Vertically | Stack::Layered => {
+                child.width().max(current.width()).min(root.width())
+            }
             Stack::Horizontally => child
                 .width()
-                .min(current.width() - last.width())
+                .min(current.width().saturating_sub(used_x))
                 .min(root.width()),
         };
         let height = match stack {
-            Stack::Vertically => child
+            Stack::Vertically | Stack::Layered => child
                 .height()
-                .min(current.height() - last.height())
+                .min(current.height().saturating_sub(used_y))
                 .min(root.height()),
             Stack::Horizontally => child.height().min(current.height()).min(root.height()),
         };
@@ -137,3 +149,45 @@ impl From<(Rect, Stack)> for Container {
         Self::new(rect, stack)
     }
 }
+
+#[test]
+fn test_nested_container_layout() {
+    use crate::text::Text;
+    let size = Size::new(6, 3);
+    let inner = Container::new(Rect::new(Size::new(6, 2)), Stack::Vertically)
+        .push(Text::new("ab").max_width(6))
+        .push(
+            Container::new(Rect::new(Size::new(6, 1)), Stack::Horizontally)
+                .push(Text::new(":").max_width(1))
+                .push(Text::new("cd").max_width(2))
+                .push(Text::new("e").max_width(3)),
+        );
+    let root = Container::new(Rect::new(size), Stack::Vertically)
+        .push(Text::new("top").max_width(6))
+        .push(inner);
+    let mut grid = Grid::new(size);
+    root.draw(&mut grid, Rect::new(size));
+    assert_eq!(grid.lines(), vec!["top   ", "ab    ", ":cde  "]);
+}
+
+#[test]
+fn test_layered_container_draws_on_top() {
+    use crate::text::Text;
+    let size = Size::new(6, 3);
+    let below = Container::new(Rect::new(size), Stack::Vertically)
+        .push(Text::new("aaaaaa").max_width(6))
+        .push(Text::new("bbbbbb").max_width(6))
+        .push(Text::new("cccccc").max_width(6));
+    let popup = Container::new(
+        Rect::with_position(Pos::new(3, 1), Size::new(2, 2)),
+        Stack::Vertically,
+    )
+    .push(Text::new("xy").max_width(2))
+    .push(Text::new("z").max_width(2));
+    let root = Container::new(Rect::new(size), Stack::Layered)
+        .push(below)
+        .push(popup);
+    let mut grid = Grid::new(size);
+    root.draw(&mut grid, Rect::new(size));
+    assert_eq!(grid.lines(), vec!["aaaaaa", "bbbxyb", "cccz c"]);
+}
@@ -0,0 +1,141 @@
+use crate::layout::{Pos, Size};
+use crossterm::style::ContentStyle;
+
+/// One character on the screen and the style it is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub symbol: char,
+    pub style: ContentStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: ' ',
+            style: ContentStyle::new(),
+        }
+    }
+}
+
+/// In memory copy of the screen widgets draw into.
+/// The runtime keeps the last frame around and only sends the cells that changed to the terminal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Grid {
+    size: Size,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(size: Size) -> Self {
+        let len = size.width as usize * size.height as usize;
+        Self {
+            size,
+            cells: vec![Cell::default(); len],
+        }
+    }
+
+    #[must_use]
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x >= self.size.width || y >= self.size.height {
+            return None;
+        }
+        Some(y as usize * self.size.width as usize + x as usize)
+    }
+
+    #[must_use]
+    pub fn get(&self, x: u16, y: u16) -> Option<&Cell> {
+        self.index(x, y).map(|idx| &self.cells[idx])
+    }
+
+    pub fn set(&mut self, x: u16, y: u16, cell: Cell) {
+        if let Some(idx) = self.index(x, y) {
+            self.cells[idx] = cell;
+        }
+    }
+
+    /// Writes `text` starting at `x`, `y`, anything past the right edge is dropped.
+    /// Returns the number of cells written.
+    pub fn set_str(&mut self, x: u16, y: u16, text: &str, style: ContentStyle) -> u16 {
+        let mut written = 0;
+        for (col, symbol) in (x..self.size.width).zip(text.chars()) {
+            self.set(col, y, Cell { symbol, style });
+            written += 1;
+        }
+        written
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    /// Cells of `next` that differ from `self` in row major order.
+    /// Every cell is returned when the sizes do not match.
+    #[must_use]
+    pub fn diff<'a>(&self, next: &'a Grid) -> Vec<(Pos, &'a Cell)> {
+        let width = next.size.width.max(1) as usize;
+        let resized = self.size != next.size;
+        next.cells
+            .iter()
+            .enumerate()
+            .filter(|(idx, cell)| resized || self.cells[*idx] != **cell)
+            .map(|(idx, cell)| {
+                let x = (idx % width) as u16;
+                let y = (idx / width) as u16;
+                (Pos { x, y }, cell)
+            })
+            .collect()
+    }
+
+    /// Text of row `y` without styling.
+    #[must_use]
+    pub fn line(&self, y: u16) -> String {
+        (0..self.size.width)
+            .filter_map(|x| self.get(x, y))
+            .map(|cell| cell.symbol)
+            .collect()
+    }
+
+    /// Text of every row without styling.
+    #[must_use]
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.size.height).map(|y| self.line(y)).collect()
+    }
+}
+
+#[test]
+fn test_grid_set_str_clips_at_edge() {
+    let mut grid = Grid::new(Size::new(5, 2));
+    let written = grid.set_str(3, 1, "hello", ContentStyle::new());
+    assert_eq!(written, 2);
+    assert_eq!(grid.lines(), vec!["     ".to_string(), "   he".to_string()]);
+}
+
+#[test]
+fn test_grid_diff_only_changed_cells() {
+    use crossterm::style::Color;
+    let prev = Grid::new(Size::new(4, 2));
+    let mut next = prev.clone();
+    let style = ContentStyle {
+        foreground_color: Some(Color::Red),
+        ..ContentStyle::new()
+    };
+    next.set_str(1, 1, "ab", style);
+    next.set_str(0, 0, " ", ContentStyle::new());
+    let changes = prev
+        .diff(&next)
+        .into_iter()
+        .map(|(pos, cell)| (pos.x, pos.y, cell.symbol))
+        .collect::<Vec<_>>();
+    assert_eq!(changes, vec![(1, 1, 'a'), (2, 1, 'b')]);
+}
+
+#[test]
+fn test_grid_diff_after_resize_is_full() {
+    let prev = Grid::new(Size::new(2, 2));
+    let next = Grid::new(Size::new(3, 1));
+    assert_eq!(prev.diff(&next).len(), 3);
+}
@@ -1,26 +1,32 @@
-use crossterm::event::KeyEventKind;
+use crossterm::event::{KeyEvent, KeyEventKind, KeyModifiers};
 
 fn strip_it(key: &str) -> Option<&str> {
     key.strip_prefix('<').and_then(|n| n.strip_suffix('>'))
 }
 
 fn decode_keys(key: &str) -> Keys {
-    strip_it(key)
-        .and_then(|n| n.split_once('-'))
-        .map(|(m, k)| {
-            let key = Key::from(k);
-            let modk = Key::from(lookup_mod_key(m));
-            Keys::KeyAndMod(key, modk)
-        })
-        .or(strip_it(key).map(|k| Keys::Key(Key::from(k))))
-        .unwrap_or(Keys::Key(Key::from(key)))
+    let Some(mut name) = strip_it(key) else {
+        return Keys::from(Key::from(key));
+    };
+    let mut mods = Modifiers::NONE;
+    // `<C-S-x>`, the key itself may be a `-` as in `<C-->`.
+    while let Some((modk, rest)) = name.split_once('-') {
+        let Some(modk) = lookup_mod_key(modk).filter(|_| !rest.is_empty()) else {
+            break;
+        };
+        mods.insert(modk);
+        name = rest;
+    }
+    Keys::new(Key::from(name), mods)
 }
 
-fn lookup_mod_key(modk: &str) -> &str {
+fn lookup_mod_key(modk: &str) -> Option<Modifiers> {
     match modk {
-        "c" => "ctrl",
-        "a" => "alt",
-        _ => modk,
+        "c" | "C" => Some(Modifiers::CTRL),
+        "a" | "A" | "m" | "M" => Some(Modifiers::ALT),
+        "s" | "S" => Some(Modifiers::SHIFT),
+        "d" | "D" => Some(Modifiers::SUPER),
+        _ => None,
     }
 }
 
@@ -34,8 +40,14 @@ pub fn string_to_keys(src: &str) -> Vec<Keys> {
                 while let Some(c) = stream.next_if(|c| c != &'>') {
                     modk.push(c);
                 }
-                modk.push(stream.next().unwrap());
-                keys.push(decode_keys(&modk));
+                match stream.next() {
+                    Some(close) => {
+                        modk.push(close);
+                        keys.push(decode_keys(&modk));
+                    }
+                    // No closing `>`, the text is typed as is.
+                    None => keys.extend(modk.chars().map(|c| Keys::from(Key::from(c)))),
+                }
             }
             _ => keys.push(decode_keys(stream.next().unwrap().to_string().as_str())),
         }
@@ -43,56 +55,160 @@ pub fn string_to_keys(src: &str) -> Vec<Keys> {
     keys
 }
 
+/// Text that `string_to_keys` parses back into `keys`.
+pub fn keys_to_string(keys: &[Keys]) -> String {
+    keys.iter().map(ToString::to_string).collect()
+}
+
 #[test]
 fn test_key_parse() {
     let left = string_to_keys("<space>abc");
     let right = vec![
-        Keys::Key(Key::Space),
-        Keys::Key(Key::LA),
-        Keys::Key(Key::LB),
-        Keys::Key(Key::LC),
+        Keys::from(Key::Space),
+        Keys::from(Key::LA),
+        Keys::from(Key::LB),
+        Keys::from(Key::LC),
     ];
 
     assert_eq!(left, right);
 }
 
-#[allow(dead_code)]
+/// Modifier keys held down along with a key.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Self = Self(0);
+    pub const SHIFT: Self = Self(1);
+    pub const CTRL: Self = Self(1 << 1);
+    pub const ALT: Self = Self(1 << 2);
+    pub const SUPER: Self = Self(1 << 3);
+
+    /// Letters used for each modifier in `<C-S-x>`, in the order they are printed.
+    const LETTERS: [(Self, char); 4] = [
+        (Self::CTRL, 'C'),
+        (Self::ALT, 'A'),
+        (Self::SHIFT, 'S'),
+        (Self::SUPER, 'D'),
+    ];
+
+    #[must_use]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[must_use]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl From<KeyModifiers> for Modifiers {
+    fn from(key: KeyModifiers) -> Self {
+        let mut mods = Self::NONE;
+        for (from, to) in [
+            (KeyModifiers::SHIFT, Self::SHIFT),
+            (KeyModifiers::CONTROL, Self::CTRL),
+            (KeyModifiers::ALT, Self::ALT),
+            (KeyModifiers::SUPER, Self::SUPER),
+        ] {
+            if key.contains(from) {
+                mods.insert(to);
+            }
+        }
+        mods
+    }
+}
+
+/// A key with the modifiers held down when it was pressed.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum Keys {
-    Key(Key),
-    KeyAndMod(Key, Key),
+pub struct Keys {
+    pub key: Key,
+    pub mods: Modifiers,
 }
 
 impl Keys {
+    /// Keys that are typed the same way compare equal:
+    /// shift is folded into characters so `<S-a>` is `A`, and `<BackTab>` is `<S-Tab>`.
+    #[must_use]
+    pub fn new(key: Key, mut mods: Modifiers) -> Self {
+        let key = match key {
+            Key::BackTab => {
+                mods.insert(Modifiers::SHIFT);
+                Key::Tab
+            }
+            key if key.name().is_none() && mods.contains(Modifiers::SHIFT) => {
+                mods.remove(Modifiers::SHIFT);
+                Key::from(key.as_char().to_ascii_uppercase())
+            }
+            key => key,
+        };
+        Self { key, mods }
+    }
+
+    /// Keys for a key event, `None` for key releases and keys we have no name for.
+    /// Held down keys repeat like presses.
+    #[must_use]
+    pub fn from_event(event: KeyEvent) -> Option<Self> {
+        if let KeyEventKind::Release = event.kind {
+            return None;
+        }
+        let keys = Self::new(Key::from(event.code), Modifiers::from(event.modifiers));
+        (!keys.is_null()).then_some(keys)
+    }
+
     pub fn is_null(&self) -> bool {
-        matches!(self, Keys::Key(Key::Null))
+        self.key == Key::Null
     }
 
+    /// Text typed by the keys, `None` while a modifier is held.
     pub fn as_char(&self) -> Option<char> {
-        if self.is_null() {
+        if self.is_null() || !self.mods.is_empty() {
             return None;
         }
-        match self {
-            Self::Key(key) => Some(key.as_char()),
-            _ => None,
-        }
+        Some(self.key.as_char())
     }
 }
 
-impl From<crossterm::event::KeyEvent> for Keys {
-    fn from(event: crossterm::event::KeyEvent) -> Self {
-        let key = Key::from(event.code);
-        let modk = Key::from(event.modifiers);
+impl From<Key> for Keys {
+    fn from(key: Key) -> Self {
+        Self::new(key, Modifiers::NONE)
+    }
+}
 
-        let kind = event.kind;
-        if !matches!(kind, KeyEventKind::Press) {
-            return Self::Key(Key::Null);
+impl std::fmt::Display for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match (self.key.name(), self.key) {
+            (Some(name), _) => name.to_string(),
+            (None, Key::Char('<')) => "lt".to_string(),
+            (None, key) => key.as_char().to_string(),
+        };
+        if self.mods.is_empty() && self.key.name().is_none() && self.key != Key::Char('<') {
+            return write!(f, "{name}");
         }
-
-        if let (_, Key::Null) = (key, modk) {
-            return Self::Key(key);
+        write!(f, "<")?;
+        for (modk, letter) in Modifiers::LETTERS {
+            if self.mods.contains(modk) {
+                write!(f, "{letter}-")?;
+            }
         }
-        Self::KeyAndMod(key, modk)
+        write!(f, "{name}>")
     }
 }
 
@@ -187,9 +303,6 @@ pub enum Key {
     Delete,
     Insert,
     Esc,
-    Ctrl,
-    Alt,
-    Shift,
     Space,
     Null,
     Colon,
@@ -218,12 +331,11 @@ impl From<u8> for Key {
 impl From<&str> for Key {
     fn from(c: &str) -> Self {
         match c.to_lowercase().as_str() {
-            "ctrl" => Key::Ctrl,
-            "alt" => Key::Alt,
             "space" => Key::Space,
             "esc" => Key::Esc,
-            "enter" => Key::Enter,
-            "backspace" => Key::Backspace,
+            "enter" | "cr" | "return" => Key::Enter,
+            "backspace" | "bs" => Key::Backspace,
+            "lt" => Key::Char('<'),
             "left" => Key::Left,
             "right" => Key::Right,
             "up" => Key::Up,
@@ -234,8 +346,9 @@ impl From<&str> for Key {
             "pagedown" => Key::PageDown,
             "tab" => Key::Tab,
             "backtab" => Key::BackTab,
-            "delete" => Key::Delete,
-            "insert" => Key::Insert,
+            "delete" | "del" => Key::Delete,
+            "insert" | "ins" => Key::Insert,
+            "nul" => Key::Null,
             "f1" => Self::from(1),
             "f2" => Self::from(2),
             "f3" => Self::from(3),
@@ -325,6 +438,7 @@ impl From<char> for Key {
             ':' => Self::Colon,
             ';' => Self::SimiColon,
             '^' => Self::Caret,
+            ' ' => Self::Space,
             _ => Self::Char(c),
         }
     }
@@ -352,7 +466,7 @@ impl From<crossterm::event::KeyCode> for Key {
             KeyCode::Char(c) => Self::from(c),
             KeyCode::Null => Key::Null,
             KeyCode::Esc => Key::Esc,
-            _ => todo!(),
+            _ => Key::Null,
         }
     }
 }
@@ -375,6 +489,44 @@ impl Key {
         }
     }
 
+    /// Name used between `<` and `>` for keys that do not print as themselves.
+    #[must_use]
+    pub fn name(self) -> Option<&'static str> {
+        let name = match self {
+            Self::Space => "Space",
+            Self::Esc => "Esc",
+            Self::Enter => "CR",
+            Self::Backspace => "BS",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::Home => "Home",
+            Self::End => "End",
+            Self::PageUp => "PageUp",
+            Self::PageDown => "PageDown",
+            Self::Tab => "Tab",
+            Self::BackTab => "BackTab",
+            Self::Delete => "Del",
+            Self::Insert => "Insert",
+            Self::F1 => "F1",
+            Self::F2 => "F2",
+            Self::F3 => "F3",
+            Self::F4 => "F4",
+            Self::F5 => "F5",
+            Self::F6 => "F6",
+            Self::F7 => "F7",
+            Self::F8 => "F8",
+            Self::F9 => "F9",
+            Self::F10 => "F10",
+            Self::F11 => "F11",
+            Self::F12 => "F12",
+            Self::Null => "Nul",
+            _ => return None,
+        };
+        Some(name)
+    }
+
     #[must_use]
     pub fn as_char(self) -> char {
         match self {
@@ -443,42 +595,65 @@ impl Key {
             Self::Colon => ':',
             Self::SimiColon => ';',
             Self::Enter => '\n',
+            Self::Space => ' ',
+            Self::Caret => '^',
             Self::Char(c) => c,
             _ => '\0',
         }
     }
 }
 
-impl From<crossterm::event::KeyModifiers> for Key {
-    fn from(key: crossterm::event::KeyModifiers) -> Self {
-        use crossterm::event::KeyModifiers;
-        if key.intersects(KeyModifiers::ALT) {
-            return Self::Alt;
-        }
-        if key.intersects(KeyModifiers::CONTROL) {
-            return Self::Ctrl;
-        }
-        // if key.intersects(KeyModifiers::SHIFT) {
-        //     return Self::Shift;
-        // }
-        Self::Null
-    }
-}
-
 #[test]
 fn test_from_crossterm_key_to_revi_key_colon() {
-    use crossterm::event::{KeyCode, KeyModifiers};
-    let (k1, k2) = (KeyCode::Char(':'), KeyModifiers::SHIFT);
-    let left = (Key::from(k1), Key::from(k2));
-    assert_eq!(left, (Key::Colon, Key::Null));
+    use crossterm::event::KeyCode;
+    let event = KeyEvent::new(KeyCode::Char(':'), KeyModifiers::SHIFT);
+    assert_eq!(Keys::from_event(event), Some(Keys::from(Key::Colon)));
 }
 
 #[test]
 fn test_from_crossterm_key_to_revi_key_upper_a() {
-    use crossterm::event::{KeyCode, KeyModifiers};
-    let (k1, k2) = (KeyCode::Char('A'), KeyModifiers::SHIFT);
-    let left = (Key::from(k1), Key::from(k2));
-    assert_eq!(left, (Key::UA, Key::Null));
+    use crossterm::event::KeyCode;
+    let event = KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT);
+    assert_eq!(Keys::from_event(event), Some(Keys::from(Key::UA)));
+    // The kitty protocol reports the unshifted key along with shift.
+    let event = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::SHIFT);
+    assert_eq!(Keys::from_event(event), Some(Keys::from(Key::UA)));
+}
+
+#[test]
+fn key_events_repeat_but_do_not_release() {
+    use crossterm::event::{KeyCode, KeyEventState};
+    let event = |kind| KeyEvent {
+        code: KeyCode::Char('j'),
+        modifiers: KeyModifiers::NONE,
+        kind,
+        state: KeyEventState::NONE,
+    };
+    let j = Some(Keys::from(Key::LJ));
+    assert_eq!(Keys::from_event(event(KeyEventKind::Press)), j);
+    assert_eq!(Keys::from_event(event(KeyEventKind::Repeat)), j);
+    assert_eq!(Keys::from_event(event(KeyEventKind::Release)), None);
+}
+
+#[test]
+fn multiple_modifiers() {
+    use crossterm::event::KeyCode;
+    let keys = string_to_keys("<C-S-Tab><C-A-j><C-->");
+    assert_eq!(
+        keys,
+        vec![
+            Keys::new(Key::Tab, Modifiers::CTRL | Modifiers::SHIFT),
+            Keys::new(Key::LJ, Modifiers::CTRL | Modifiers::ALT),
+            Keys::new(Key::Char('-'), Modifiers::CTRL),
+        ]
+    );
+    assert_eq!(keys_to_string(&keys), "<C-S-Tab><C-A-j><C-->");
+    let backtab = KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT);
+    assert_eq!(Keys::from_event(backtab), string_to_keys("<S-Tab>").pop());
+    // With the kitty protocol <C-i> no longer arrives as <Tab>.
+    let ctrl_i = KeyEvent::new(KeyCode::Char('i'), KeyModifiers::CONTROL);
+    assert_ne!(Keys::from_event(ctrl_i), string_to_keys("<Tab>").pop());
+    assert_eq!(string_to_keys("<S-x>"), string_to_keys("X"));
 }
 
 #[test]
@@ -487,6 +662,27 @@ fn string_to_keys_parse() {
     assert_ne!(string_to_keys("B"), string_to_keys("b"));
 }
 
+#[test]
+fn keys_print_and_parse_back() {
+    for src in [
+        "<space>w",
+        ":w<enter>",
+        "<C-x>",
+        "<A-b>",
+        "<lt>a",
+        "<esc>jk",
+    ] {
+        let keys = string_to_keys(src);
+        assert_eq!(string_to_keys(&keys_to_string(&keys)), keys, "{src}");
+    }
+    assert_eq!(string_to_keys("<CR>"), string_to_keys("<enter>"));
+    assert_eq!(string_to_keys(" "), string_to_keys("<space>"));
+    assert_eq!(
+        string_to_keys("<a"),
+        vec![Keys::from(Key::Char('<')), Keys::from(Key::LA)]
+    );
+}
+
 #[macro_export]
 macro_rules! keys {
     ( $( $x:ident $(($($args:expr),*))? ),* ) => {
@@ -1,37 +1,25 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum Alignment {
+    #[default]
     Left,
     Right,
     Center,
 }
-impl Default for Alignment {
-    fn default() -> Self {
-        Self::Left
-    }
-}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum Spacing {
     Fill,
+    #[default]
     Shrink,
 }
 
-impl Default for Spacing {
-    fn default() -> Self {
-        Self::Shrink
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Stack {
     Vertically,
+    #[default]
     Horizontally,
-}
-
-impl Default for Stack {
-    fn default() -> Self {
-        Self::Horizontally
-    }
+    /// Children are drawn on top of each other, later ones over earlier ones.
+    Layered,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -1,5 +1,7 @@
 pub mod application;
+pub mod backend;
 pub mod container;
+pub mod grid;
 mod key;
 pub mod layout;
 pub mod runtime;
@@ -9,10 +11,12 @@ pub mod widget;
 
 pub use crossterm::{cursor::SetCursorStyle, event, style, style::Attribute, style::Color, Result};
 
+pub use key::keys_to_string;
 pub use key::string_to_keys;
 pub use key::Key;
 pub use key::Keys;
-pub use subscription::{Command, Subscription};
+pub use key::Modifiers;
+pub use subscription::{Command, Sender, Subscription};
 
 use layout::Size;
 
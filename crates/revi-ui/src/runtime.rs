@@ -1,43 +1,189 @@
 use crate::{
     application::App,
+    backend::{Backend, CrosstermBackend},
+    grid::Grid,
     layout::{Pos, Rect, Size},
+    subscription::{Input, Recipe, Sender, Subscription},
 };
 use crossterm::{
-    cursor::{Hide, MoveTo, RestorePosition, SavePosition, Show},
-    event,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    cursor::{Hide, RestorePosition, SavePosition, Show},
+    event::{
+        self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, Event, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
     QueueableCommand, Result,
 };
-use std::io::Stdout;
-use std::{io::Write, time::Duration};
+use std::collections::{HashMap, HashSet};
+use std::io::{Stdout, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Once};
+use std::thread;
 
-fn render_app<A>(w: &mut Stdout, app: &mut A) -> Result<()>
+/// Set while the terminal is in raw mode and on the alternate screen.
+static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Set while the terminal reports keys with the kitty keyboard protocol.
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
+/// Set while the terminal reports mouse events.
+static MOUSE_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+/// Puts the terminal back the way it was before `run` took it over.
+/// Safe to call more than once, only the first call after `enter_terminal` does anything.
+fn restore_terminal() {
+    if !TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let mut writer = std::io::stdout();
+    if KEYBOARD_ENHANCED.swap(false, Ordering::SeqCst) {
+        let _ = writer.queue(PopKeyboardEnhancementFlags);
+    }
+    if MOUSE_CAPTURED.swap(false, Ordering::SeqCst) {
+        let _ = writer.queue(DisableMouseCapture);
+    }
+    let _ = writer.queue(DisableBracketedPaste);
+    let _ = writer.queue(DisableFocusChange);
+    let _ = disable_raw_mode();
+    let _ = writer.queue(LeaveAlternateScreen);
+    let _ = writer.queue(RestorePosition);
+    let _ = writer.queue(Show);
+    let _ = writer.flush();
+}
+
+/// Raw mode, the alternate screen and the events the runtime asks the terminal to report.
+fn enter_screen(writer: &mut Stdout) -> Result<()> {
+    writer.queue(EnterAlternateScreen)?;
+    writer.queue(SavePosition)?;
+    writer.queue(Hide)?;
+    // Pastes arrive as one event instead of a key press per character.
+    writer.queue(EnableBracketedPaste)?;
+    writer.queue(EnableFocusChange)?;
+    enable_raw_mode()?;
+    TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+fn enter_terminal(writer: &mut Stdout) -> Result<()> {
+    enter_screen(writer)?;
+    // Asking the terminal reads from stdin, so this has to happen before the event reader starts.
+    // With the protocol `<C-i>` and `<Tab>` or `<C-[>` and `<Esc>` are different keys.
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        writer.queue(PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES,
+        ))?;
+        KEYBOARD_ENHANCED.store(true, Ordering::SeqCst);
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Asks the runtime to give the terminal back to the shell and stop the process, like `<C-z>` in a shell.
+/// Does nothing while the runtime is not running or on platforms without job control.
+pub fn suspend() {
+    #[cfg(unix)]
+    if TERMINAL_ACTIVE.load(Ordering::SeqCst) {
+        let _ = signal_hook::low_level::raise(signal_hook::consts::SIGTSTP);
+    }
+}
+
+/// Restores the terminal, stops until the shell continues the process and takes the terminal over again.
+#[cfg(unix)]
+fn suspend_terminal() -> Result<()> {
+    let enhanced = KEYBOARD_ENHANCED.load(Ordering::SeqCst);
+    restore_terminal();
+    signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+    // Running again after `fg`.
+    let mut writer = std::io::stdout();
+    enter_screen(&mut writer)?;
+    // The event reader owns stdin by now, so reuse the answer from startup instead of asking again.
+    if enhanced {
+        writer.queue(PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES,
+        ))?;
+        KEYBOARD_ENHANCED.store(true, Ordering::SeqCst);
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn suspend_terminal() -> Result<()> {
+    Ok(())
+}
+
+/// Restores the terminal when `run` returns early with an error or unwinds.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Restores the terminal before the default hook prints the panic,
+/// otherwise the message is lost with the alternate screen.
+/// Installed once, later runs would wrap the hook again.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+    });
+}
+
+/// Renders `app` to `backend`, only the cells that changed since `last_frame` are drawn.
+pub fn render<A, B>(backend: &mut B, app: &A, last_frame: &mut Grid) -> Result<()>
 where
     A: App,
+    B: Backend,
 {
-    w.queue(Hide)?;
-    if let Some(Pos { x, y }) = app.cursor_pos() {
-        w.queue(MoveTo(x, y))?;
-    }
-    if let Some(cs) = app.cursor_shape() {
-        w.queue(cs)?;
-    }
+    backend.hide_cursor()?;
     let widgets = app.view();
     let width = widgets.width();
     let height = widgets.height();
     let app_size = Size { width, height };
     let app_pos = Pos { x: 0, y: 0 };
-    w.queue(SavePosition)?;
-    widgets.draw(w, Rect::with_position(app_pos, app_size));
-    w.queue(RestorePosition)?;
-    if app.cursor_shape().is_some() {
-        w.queue(Show)?;
+    let mut frame = Grid::new(app_size);
+    widgets.draw(&mut frame, Rect::with_position(app_pos, app_size));
+    if frame.size() != last_frame.size() {
+        backend.clear()?;
+    }
+    backend.draw(last_frame.diff(&frame))?;
+    *last_frame = frame;
+    if let Some(pos) = app.cursor_pos() {
+        backend.move_cursor(pos)?;
+    }
+    if let Some(cs) = app.cursor_shape() {
+        backend.show_cursor(cs)?;
+    }
+    backend.flush()?;
+    Ok(())
+}
+
+/// Turns mouse reporting on or off when the app changed its mind.
+fn sync_mouse_capture<A, B>(backend: &mut B, app: &A) -> Result<()>
+where
+    A: App,
+    B: Backend,
+{
+    let mouse = app.mouse_capture();
+    if mouse != MOUSE_CAPTURED.load(Ordering::SeqCst) {
+        backend.set_mouse_capture(mouse)?;
+        backend.flush()?;
+        MOUSE_CAPTURED.store(mouse, Ordering::SeqCst);
     }
-    w.flush()?;
     Ok(())
 }
 
-fn update<A>(app: &mut A, message: A::Message)
+/// Hands `message` to the app, along with every message `update` returns after it.
+pub fn update<A>(app: &mut A, message: A::Message)
 where
     A: App,
 {
@@ -51,32 +197,161 @@ pub fn run<A>(app: &mut A) -> Result<()>
 where
     A: App,
 {
+    install_panic_hook();
     let mut writer = std::io::stdout();
-    writer.queue(EnterAlternateScreen)?;
-    writer.queue(SavePosition)?;
-    writer.queue(Hide)?;
-    enable_raw_mode()?;
-    writer.flush()?;
-    let mut subscriptions = app.subscription();
-    render_app(&mut writer, app)?;
-    while app.quit() {
-        if event::poll(Duration::from_millis(50)).unwrap_or(false) {
-            let event = event::read()?;
-            for sub in subscriptions.0.iter() {
+    let _guard = TerminalGuard;
+    enter_terminal(&mut writer)?;
+    let mut backend = CrosstermBackend::new(writer);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| event_loop(&mut backend, app)));
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            app.emergency_save();
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+/// Background sources the app currently subscribes to, by id.
+struct Sources {
+    running: HashMap<String, Arc<AtomicBool>>,
+}
+
+impl Sources {
+    fn new() -> Self {
+        Self {
+            running: HashMap::new(),
+        }
+    }
+
+    /// Starts recipes with new ids and stops the ones the app no longer asks for.
+    fn sync<T>(&mut self, recipes: Vec<Recipe<T>>, tx: &mpsc::Sender<Input<T>>)
+    where
+        T: Send + 'static,
+    {
+        let ids = recipes
+            .iter()
+            .map(|r| r.id.as_str())
+            .collect::<HashSet<_>>();
+        self.running.retain(|id, alive| {
+            let keep = ids.contains(id.as_str());
+            if !keep {
+                alive.store(false, Ordering::SeqCst);
+            }
+            keep
+        });
+        for recipe in recipes {
+            if self.running.contains_key(&recipe.id) {
+                continue;
+            }
+            let alive = Arc::new(AtomicBool::new(true));
+            self.running.insert(recipe.id.clone(), alive.clone());
+            recipe.start(Sender::new(tx.clone(), alive));
+        }
+    }
+
+    fn stop_all(&mut self) {
+        for alive in self.running.values() {
+            alive.store(false, Ordering::SeqCst);
+        }
+        self.running.clear();
+    }
+}
+
+/// Reads terminal events on their own thread so the runtime can sleep until something happens.
+fn spawn_event_reader<T: Send + 'static>(tx: mpsc::Sender<Input<T>>) {
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if tx.send(Input::Event(event)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Turns `SIGTSTP` into input, the event loop restores the terminal before the process stops.
+#[cfg(unix)]
+fn spawn_signal_reader<T: Send + 'static>(tx: mpsc::Sender<Input<T>>) -> Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGTSTP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if tx.send(Input::Suspend).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn spawn_signal_reader<T: Send + 'static>(_tx: mpsc::Sender<Input<T>>) -> Result<()> {
+    Ok(())
+}
+
+fn handle_input<A>(
+    app: &mut A,
+    subscription: &Subscription<A::Message>,
+    input: Input<A::Message>,
+    last_frame: &mut Grid,
+) -> Result<()>
+where
+    A: App,
+{
+    match input {
+        Input::Event(event) => {
+            for sub in subscription.events.iter() {
                 let Some(message) = sub(event.clone()) else {
                     continue;
                 };
                 update(app, message);
             }
-            render_app(&mut writer, app)?;
-            subscriptions = app.subscription();
+        }
+        Input::Message(message) => update(app, message),
+        Input::Suspend => {
+            suspend_terminal()?;
+            // The screen is blank after coming back and files may have changed in the meantime.
+            *last_frame = Grid::default();
+            handle_input(
+                app,
+                subscription,
+                Input::Event(Event::FocusGained),
+                last_frame,
+            )?;
         }
     }
+    Ok(())
+}
 
-    disable_raw_mode()?;
-    writer.queue(LeaveAlternateScreen)?;
-    writer.queue(RestorePosition)?;
-    writer.queue(Show)?;
-    writer.flush()?;
+fn event_loop<A, B>(backend: &mut B, app: &mut A) -> Result<()>
+where
+    A: App,
+    B: Backend,
+{
+    let (tx, rx) = mpsc::channel();
+    spawn_event_reader(tx.clone());
+    spawn_signal_reader(tx.clone())?;
+    let mut sources = Sources::new();
+    let mut subscription = app.subscription();
+    sources.sync(std::mem::take(&mut subscription.recipes), &tx);
+    let mut last_frame = Grid::default();
+    sync_mouse_capture(backend, app)?;
+    render(backend, app, &mut last_frame)?;
+    while app.quit() {
+        let Ok(input) = rx.recv() else {
+            break;
+        };
+        // Handle everything that queued up while we slept, then draw once.
+        for input in std::iter::once(input).chain(rx.try_iter()) {
+            handle_input(app, &subscription, input, &mut last_frame)?;
+            subscription = app.subscription();
+            sources.sync(std::mem::take(&mut subscription.recipes), &tx);
+            if !app.quit() {
+                break;
+            }
+        }
+        sync_mouse_capture(backend, app)?;
+        render(backend, app, &mut last_frame)?;
+    }
+    sources.stop_all();
     Ok(())
 }
@@ -1,16 +1,143 @@
 use super::event::Event;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
 
 pub type Command<T> = Box<dyn Fn(Event) -> Option<T>>;
 
-pub struct Subscription<T>(pub Vec<Command<T>>);
+/// Everything that wakes up the runtime.
+pub(crate) enum Input<T> {
+    Event(Event),
+    Message(T),
+    /// The process got `SIGTSTP`.
+    Suspend,
+}
+
+/// Handle a background source uses to hand messages to the app.
+pub struct Sender<T> {
+    tx: mpsc::Sender<Input<T>>,
+    alive: Arc<AtomicBool>,
+}
+
+impl<T> Sender<T> {
+    pub(crate) fn new(tx: mpsc::Sender<Input<T>>, alive: Arc<AtomicBool>) -> Self {
+        Self { tx, alive }
+    }
+
+    /// Sends `message` to the app.
+    /// Returns `false` once the app no longer subscribes to the source, the source should stop then.
+    pub fn send(&self, message: T) -> bool {
+        self.is_alive() && self.tx.send(Input::Message(message)).is_ok()
+    }
+
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            alive: self.alive.clone(),
+        }
+    }
+}
+
+/// A background message source.
+/// The runtime starts it on its own thread the first time `id` shows up in a subscription
+/// and stops it once the id is missing from the app's subscription.
+pub struct Recipe<T> {
+    pub(crate) id: String,
+    pub(crate) spawn: Box<dyn FnOnce(Sender<T>) + Send>,
+}
+
+impl<T: Send + 'static> Recipe<T> {
+    pub(crate) fn start(self, sender: Sender<T>) {
+        let spawn = self.spawn;
+        thread::spawn(move || spawn(sender));
+    }
+}
+
+pub struct Subscription<T> {
+    pub(crate) events: Vec<Command<T>>,
+    pub(crate) recipes: Vec<Recipe<T>>,
+}
 
 impl<T> Subscription<T> {
     pub fn none() -> Self {
-        Self(vec![])
+        Self {
+            events: vec![],
+            recipes: vec![],
+        }
     }
 
+    /// Turns terminal events into messages.
     pub fn push(mut self, func: impl Fn(Event) -> Option<T> + 'static) -> Self {
-        self.0.push(Box::new(func));
+        self.events.push(Box::new(func));
+        self
+    }
+
+    /// Runs `func` on its own thread for as long as the app subscribes to `id`.
+    pub fn run(
+        mut self,
+        id: impl Into<String>,
+        func: impl FnOnce(Sender<T>) + Send + 'static,
+    ) -> Self {
+        self.recipes.push(Recipe {
+            id: id.into(),
+            spawn: Box::new(func),
+        });
         self
     }
+
+    /// Sends `message` once after `delay`.
+    /// Use a new `id` to restart the timer.
+    pub fn after(self, id: impl Into<String>, delay: Duration, message: T) -> Self
+    where
+        T: Send + 'static,
+    {
+        self.run(id, move |sender| {
+            thread::sleep(delay);
+            sender.send(message);
+        })
+    }
+
+    /// Sends the message built by `func` every `interval`.
+    pub fn every(
+        self,
+        id: impl Into<String>,
+        interval: Duration,
+        func: impl Fn() -> T + Send + 'static,
+    ) -> Self
+    where
+        T: Send + 'static,
+    {
+        self.run(id, move |sender| loop {
+            thread::sleep(interval);
+            if !sender.send(func()) {
+                break;
+            }
+        })
+    }
+}
+
+#[test]
+fn test_sender_stops_when_not_alive() {
+    let (tx, rx) = mpsc::channel();
+    let alive = Arc::new(AtomicBool::new(true));
+    let sender = Sender::new(tx, alive.clone());
+    assert!(sender.send(1));
+    alive.store(false, Ordering::SeqCst);
+    assert!(!sender.send(2));
+    let received = rx
+        .try_iter()
+        .filter_map(|input| match input {
+            Input::Message(m) => Some(m),
+            Input::Event(_) | Input::Suspend => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(received, vec![1]);
 }
@@ -1,8 +1,8 @@
+use crate::grid::Grid;
 use crate::layout::{Alignment, Rect};
 use crate::widget::Widget;
-use crossterm::style::{Attribute, Color, ContentStyle, ResetColor, SetAttribute, SetStyle};
-use crossterm::{cursor, queue, style};
-use std::io::Stdout;
+use crossterm::style;
+use crossterm::style::{Color, ContentStyle};
 #[derive(Debug, Default, Clone)]
 pub struct Text {
     content: String,
@@ -97,19 +97,12 @@ impl Widget for Text {
         self.height
     }
 
-    fn draw(&self, stdout: &mut Stdout, bounds: Rect) {
-        queue!(stdout, SetStyle(self.style)).expect("failed to set style");
+    fn draw(&self, grid: &mut Grid, bounds: Rect) {
         let width = bounds.width() as usize;
         let x = bounds.x() + self.x();
         let y = bounds.y() + self.y();
-        queue!(
-            stdout,
-            cursor::MoveTo(x, y),
-            style::Print(format_line(&self.content, width, &self.align)),
-        )
-        .expect("Failed to queue Text");
-        queue!(stdout, ResetColor, SetAttribute(Attribute::Reset))
-            .expect("failed to queue reset color and  attribute");
+        let line = format_line(&self.content, width, &self.align);
+        grid.set_str(x, y, &line, self.style);
     }
     fn debug_name(&self) -> String {
         self.comment.clone().unwrap_or_default()
@@ -1,11 +1,10 @@
-use crate::{layout::Rect, text::Text};
-use std::io::Stdout;
+use crate::{grid::Grid, layout::Rect, text::Text};
 pub trait Widget: std::fmt::Debug + dyn_clone::DynClone {
     fn x(&self) -> u16;
     fn y(&self) -> u16;
     fn width(&self) -> u16;
     fn height(&self) -> u16;
-    fn draw(&self, stdout: &mut Stdout, bounds: Rect);
+    fn draw(&self, grid: &mut Grid, bounds: Rect);
     fn debug_name(&self) -> String {
         "DEFAULT".to_string()
     }
@@ -48,8 +47,8 @@ impl BoxWidget {
         self.widget.height()
     }
 
-    pub fn draw(&self, stdout: &mut Stdout, bounds: Rect) {
-        self.widget.draw(stdout, bounds);
+    pub fn draw(&self, grid: &mut Grid, bounds: Rect) {
+        self.widget.draw(grid, bounds);
     }
 
     #[must_use]
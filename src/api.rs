@@ -1,6 +1,11 @@
 use crate::state::State;
-use rhai::{CustomType, Engine, EvalAltResult, Scope, TypeBuilder, AST};
-use std::{any::Any, cell::RefCell, rc::Rc};
+use crate::Mode;
+use rhai::{
+    CallFnOptions, CustomType, Dynamic, Engine, EvalAltResult, FuncArgs, Scope, TypeBuilder, AST,
+};
+use std::{cell::RefCell, rc::Rc};
+
+const INIT: &str = "./userspace/init.rhai";
 
 #[derive(Debug, Default)]
 pub struct Rhai {
@@ -10,11 +15,49 @@ pub struct Rhai {
 }
 
 impl Rhai {
+    /// Runs `init.rhai`, the functions it defines stay around to be called as hooks.
     pub fn eval_init(&mut self) {
+        self.ast = self.engine.compile_file(INIT.into()).unwrap();
         self.engine
-            .eval_file_with_scope::<()>(&mut self.scope, "./userspace/init.rhai".into())
+            .run_ast_with_scope(&mut self.scope, &self.ast)
             .unwrap();
     }
+
+    /// Calls the function `name` from `init.rhai` with `this` bound to `revi`.
+    /// `Ok(None)` when `init.rhai` does not define it.
+    fn call_hook(&mut self, name: &str, args: impl FuncArgs) -> Result<Option<Dynamic>, String> {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return Ok(None);
+        }
+        let mut this = self
+            .scope
+            .get_value::<ContextRhaiApi>("revi")
+            .map(Dynamic::from)
+            .unwrap_or_default();
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut this);
+        self.engine
+            .call_fn_with_options(options, &mut self.scope, &self.ast, name, args)
+            .map(Some)
+            .map_err(|err| format!("{name}: {err}"))
+    }
+
+    /// Text the `session_save` hook adds to a session.
+    pub fn session_save(&mut self) -> Result<Option<String>, String> {
+        match self.call_hook("session_save", ())? {
+            Some(data) => data
+                .into_string()
+                .map(Some)
+                .map_err(|ty| format!("session_save: returned {ty}, not a string")),
+            None => Ok(None),
+        }
+    }
+
+    /// Hands the text `session_save` added to a session to the `session_load` hook.
+    pub fn session_load(&mut self, data: String) -> Result<(), String> {
+        self.call_hook("session_load", (data,)).map(|_| ())
+    }
 }
 
 pub fn init(state: State) -> Result<(Rc<RefCell<State>>, Rhai), Box<EvalAltResult>> {
@@ -35,19 +78,66 @@ impl ContextRhaiApi {
     fn set_cursor_row(&mut self, row: i64) {
         self.0.borrow_mut().set_cursor_row(row as usize);
     }
+    fn nmap(&mut self, lhs: &str, rhs: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Normal, lhs, rhs, true, false, None);
+    }
+    fn nmap_desc(&mut self, lhs: &str, rhs: &str, desc: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Normal, lhs, rhs, true, false, Some(desc));
+    }
+    fn nnoremap(&mut self, lhs: &str, rhs: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Normal, lhs, rhs, false, false, None);
+    }
+    fn nnoremap_desc(&mut self, lhs: &str, rhs: &str, desc: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Normal, lhs, rhs, false, false, Some(desc));
+    }
+    fn imap(&mut self, lhs: &str, rhs: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Insert, lhs, rhs, true, false, None);
+    }
+    fn imap_desc(&mut self, lhs: &str, rhs: &str, desc: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Insert, lhs, rhs, true, false, Some(desc));
+    }
+    fn inoremap(&mut self, lhs: &str, rhs: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Insert, lhs, rhs, false, false, None);
+    }
+    fn inoremap_desc(&mut self, lhs: &str, rhs: &str, desc: &str) {
+        self.0
+            .borrow_mut()
+            .add_mapping(Mode::Insert, lhs, rhs, false, false, Some(desc));
+    }
 }
 impl CustomType for ContextRhaiApi {
     fn build(mut builder: TypeBuilder<Self>) {
         builder
             .with_name("Context")
-            .with_fn("move_cursor_down", Self::move_cursor_down);
-            // .with_fn("nmap", Self::nmap_from_str)
+            .with_fn("move_cursor_down", Self::move_cursor_down)
+            .with_fn("nmap", Self::nmap)
+            .with_fn("nmap", Self::nmap_desc)
+            .with_fn("nnoremap", Self::nnoremap)
+            .with_fn("nnoremap", Self::nnoremap_desc)
+            .with_fn("imap", Self::imap)
+            .with_fn("imap", Self::imap_desc)
+            .with_fn("inoremap", Self::inoremap)
+            .with_fn("inoremap", Self::inoremap_desc)
             // .with_fn("nmap", Self::nmap_function)
-            .with_fn("set_cursor_row", Self::set_cursor_row)
-            // .with_fn("set_cursor_col", Self::set_cursor_col)
-            .with_fn("set_scroll_row", Self::set_scroll_row)
-            // .with_fn("message", Self::message)
-            // .with_fn("export_command", Self::export_command)
-            // .with_get_set("mode", Self::get_mode, Self::set_mode);
+            .with_fn("set_cursor_row", Self::set_cursor_row);
+        // .with_fn("set_cursor_col", Self::set_cursor_col)
+        // .with_fn("set_scroll_row", Self::set_scroll_row)
+        // .with_fn("message", Self::message)
+        // .with_fn("export_command", Self::export_command)
+        // .with_get_set("mode", Self::get_mode, Self::set_mode);
     }
 }
@@ -1,5 +1,10 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
 use std::{fs::OpenOptions, io::BufWriter};
 
+use crate::map_keys::ModeMaps;
+use crate::marks::Marks;
 use revi_ui::layout::{Pos, Size};
 use ropey::Rope;
 
@@ -138,11 +143,42 @@ impl Cursor {
     }
 }
 
+/// Name given to buffers that are not backed by a file.
+pub const NO_NAME: &str = "N/A";
+
+/// The file as the buffer last read or wrote it, to notice when another program changes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskStamp {
+    modified: SystemTime,
+    hash: u64,
+}
+
+impl DiskStamp {
+    fn read(path: &str) -> Option<Self> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+        let contents = std::fs::read(path).ok()?;
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        Some(Self {
+            modified,
+            hash: hasher.finish(),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Buffer {
     pub name: String,
     rope: Rope,
     cursor: Cursor,
+    /// Buffer has edits that are not written to disk.
+    pub modified: bool,
+    /// Edits made since the swap file was last written.
+    pub unswapped_changes: usize,
+    /// Mappings made with `<buffer>`, only used while this buffer has focus.
+    pub maps: ModeMaps,
+    pub marks: Marks,
+    disk: Option<DiskStamp>,
 }
 
 impl Buffer {
@@ -152,10 +188,72 @@ impl Buffer {
             name: path.into(),
             rope: Rope::from_str(&src),
             cursor: Cursor::default(),
+            modified: false,
+            unswapped_changes: 0,
+            maps: ModeMaps::default(),
+            marks: Marks::default(),
+            disk: DiskStamp::read(path),
+        }
+    }
+
+    pub fn new_str(name: impl Into<String>, contents: &str) -> Self {
+        Self {
+            name: name.into(),
+            rope: Rope::from_str(contents),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_scratch(&self) -> bool {
+        self.name == NO_NAME
+    }
+
+    pub fn get_rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    /// Replaces the contents of the buffer with text recovered from a swap file.
+    pub fn recover(&mut self, text: &str) {
+        self.rope = Rope::from_str(text);
+        self.cursor = Cursor::default();
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        self.modified = true;
+        self.unswapped_changes += 1;
+    }
+
+    /// Inserts `text` at char index `idx`, moving marks after it along.
+    fn insert_text(&mut self, idx: usize, text: &str) {
+        self.rope.insert(idx, text);
+        let len = text.chars().count();
+        self.marks.inserted(idx, len);
+        self.record_change(idx, idx + len.saturating_sub(1));
+        self.touch();
+    }
+
+    /// Removes the chars in `range`, moving marks after it back.
+    fn remove_text(&mut self, range: std::ops::Range<usize>) {
+        if range.is_empty() {
+            return;
         }
+        let start = range.start;
+        self.rope.remove(range.clone());
+        self.marks.removed(range);
+        self.record_change(start, start);
+        self.touch();
+    }
+
+    fn record_change(&mut self, start: usize, end: usize) {
+        let rope = &self.rope;
+        let line = rope.char_to_line(start.min(rope.len_chars()));
+        self.marks.add_change(start, end, |idx| {
+            rope.char_to_line(idx.min(rope.len_chars())) == line
+        });
     }
 
-    pub fn save(&self, filename: Option<String>) -> std::io::Result<()> {
+    pub fn save(&mut self, filename: Option<String>) -> std::io::Result<()> {
         let name = match filename {
             Some(ref n) => n,
             None => &self.name,
@@ -166,6 +264,40 @@ impl Buffer {
             .truncate(true)
             .open(name)?;
         self.rope.write_to(BufWriter::new(file))?;
+        if filename.is_none() {
+            self.modified = false;
+            self.disk = DiskStamp::read(&self.name);
+        }
+        Ok(())
+    }
+
+    /// Checks whether another program changed the file since the buffer last read or wrote it.
+    /// Each change is reported once, a file that was only touched or got deleted is not reported.
+    pub fn changed_on_disk(&mut self) -> bool {
+        if self.is_scratch() {
+            return false;
+        }
+        let modified = std::fs::metadata(&self.name)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified == self.disk.map(|d| d.modified) {
+            return false;
+        }
+        let disk = DiskStamp::read(&self.name);
+        let changed = disk.is_some() && disk.map(|d| d.hash) != self.disk.map(|d| d.hash);
+        self.disk = disk;
+        changed
+    }
+
+    /// Replaces the contents of the buffer with the file on disk, keeping the cursor on the same line if it can.
+    pub fn reload(&mut self, size: &Size) -> std::io::Result<()> {
+        let src = std::fs::read_to_string(&self.name)?;
+        self.rope = Rope::from_str(&src);
+        self.modified = false;
+        self.unswapped_changes = 0;
+        self.disk = DiskStamp::read(&self.name);
+        let idx = self.char_at(self.cursor.pos, false);
+        self.set_cursor_char(idx, size);
         Ok(())
     }
 
@@ -243,15 +375,341 @@ impl Buffer {
 
     pub fn insert(&mut self, text: impl Into<String>) {
         let text = text.into();
-        let row = self.cursor.pos.y as usize;
-        let col = self.cursor.pos.x as usize;
-        let char_idx = self.rope.line_to_char(row);
-        self.rope.insert(char_idx + col, &text);
+        let idx = self.cursor_char();
+        self.insert_text(idx, &text);
         let col = text.len();
         self.cursor.add_col(col);
         if text.contains('\n') {
             self.cursor.new_line();
         }
+        // leaving insert mode puts the cursor back on the last typed char
+        self.cursor.max.x = self.cursor.pos.x;
+    }
+
+    /// Index of the last line, the empty line after a trailing newline does not count.
+    pub fn last_line(&self) -> usize {
+        let lines = self.rope.len_lines();
+        if lines > 1 && self.rope.line(lines - 1).len_chars() == 0 {
+            return lines - 2;
+        }
+        lines.saturating_sub(1)
+    }
+
+    /// Char index shown at `pos` in the text area.
+    /// Positions past the end of a line land on its last character,
+    /// or just after it with `past_end` as insert mode allows.
+    pub fn char_at(&self, pos: Pos, past_end: bool) -> usize {
+        let row = (self.cursor.scroll.y + pos.y) as usize;
+        let col = (self.cursor.scroll.x + pos.x) as usize;
+        self.line_col_to_char(row, col, past_end)
+    }
+
+    /// Char index of `col` in line `row`, both are clamped to the text like `char_at` does.
+    pub fn line_col_to_char(&self, row: usize, col: usize, past_end: bool) -> usize {
+        let row = row.min(self.last_line());
+        let line = self.rope.line(row);
+        let newline = usize::from(line.chars().last() == Some('\n'));
+        let len = line.len_chars() - newline;
+        let max_col = if past_end { len } else { len.saturating_sub(1) };
+        self.rope.line_to_char(row) + col.min(max_col)
+    }
+
+    /// Char index of the first char of `line` that is not a space or tab.
+    pub fn first_non_blank(&self, line: usize) -> usize {
+        let line = line.min(self.last_line());
+        let blanks = self
+            .rope
+            .line(line)
+            .chars()
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .count();
+        self.line_col_to_char(line, blanks, false)
+    }
+
+    /// Line and column of char index `idx`.
+    pub fn line_col(&self, idx: usize) -> (usize, usize) {
+        let idx = idx.min(self.rope.len_chars());
+        let line = self.rope.char_to_line(idx);
+        (line, idx - self.rope.line_to_char(line))
+    }
+
+    /// Text of `line` without its newline.
+    pub fn line_text(&self, line: usize) -> String {
+        match self.rope.get_line(line) {
+            Some(text) => text.to_string().trim_end_matches(['\n', '\r']).to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Adds the cursor position to the jumplist, replacing older entries on its line.
+    pub fn add_jump(&mut self, seq: u64) {
+        let idx = self.cursor_char();
+        let (line, _) = self.line_col(idx);
+        let rope = &self.rope;
+        self.marks.add_jump(seq, idx, |old| {
+            rope.char_to_line(old.min(rope.len_chars())) == line
+        });
+    }
+
+    /// Char index of the cursor in the buffer.
+    pub fn cursor_char(&self) -> usize {
+        let pos = self.cursor.pos();
+        let row = (pos.y as usize).min(self.rope.len_lines().saturating_sub(1));
+        let line = self.rope.line(row);
+        let col = (pos.x as usize).min(line.len_chars());
+        self.rope.line_to_char(row) + col
+    }
+
+    /// Scrolls so `line` and `col` are the first ones shown, the cursor moves along with the text.
+    pub fn set_scroll(&mut self, line: usize, col: usize) {
+        let pos = self.cursor.pos();
+        let line = line.min(self.last_line()) as u16;
+        let col = col as u16;
+        self.cursor.scroll = Pos::new(col, line);
+        self.cursor.pos = Pos::new(pos.x.saturating_sub(col), pos.y.saturating_sub(line));
+    }
+
+    /// Puts the cursor on char index `idx`, scrolling so it stays inside a view of `size`.
+    pub fn set_cursor_char(&mut self, idx: usize, size: &Size) {
+        let idx = idx.min(self.rope.len_chars());
+        let row = self.rope.char_to_line(idx);
+        let col = idx - self.rope.line_to_char(row);
+        let (row, col) = (row as u16, col as u16);
+        let height = size.height.max(1);
+        let width = size.width.max(1);
+        let scroll = &mut self.cursor.scroll;
+        if row < scroll.y {
+            scroll.y = row;
+        } else if row >= scroll.y + height {
+            scroll.y = row + 1 - height;
+        }
+        if col < scroll.x {
+            scroll.x = col;
+        } else if col >= scroll.x + width {
+            scroll.x = col + 1 - width;
+        }
+        self.cursor.pos = Pos::new(col - scroll.x, row - scroll.y);
+        self.cursor.max.x = self.cursor.pos.x;
+    }
+
+    /// Inserts pasted `text` as a single edit.
+    /// With `after` the text goes after the character under the cursor and the cursor ends on
+    /// the last pasted character, like `p` in normal mode, otherwise it ends after the text.
+    pub fn paste(&mut self, text: &str, after: bool, size: &Size) {
+        if text.is_empty() {
+            return;
+        }
+        let mut idx = self.cursor_char();
+        if after && self.rope.get_char(idx).is_some_and(|c| c != '\n') {
+            idx += 1;
+        }
+        self.insert_text(idx, text);
+        let len = text.chars().count();
+        let end = if after { idx + len - 1 } else { idx + len };
+        self.set_cursor_char(end, size);
+    }
+
+    /// Removes the chars in `range` and puts the cursor where they were.
+    pub fn remove(&mut self, range: std::ops::Range<usize>, size: &Size) {
+        if range.is_empty() {
+            return;
+        }
+        let start = range.start;
+        self.remove_text(range);
+        self.set_cursor_char(start, size);
+    }
+
+    /// Leading spaces and tabs of `line`.
+    pub fn indent(&self, line: usize) -> String {
+        let line = line.min(self.last_line());
+        self.rope
+            .line(line)
+            .chars()
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .collect()
+    }
+
+    /// Adds an empty line below or above `line` with `indent`, and puts the cursor at its end.
+    /// Returns the char index the new line starts at.
+    pub fn open_line(&mut self, line: usize, below: bool, indent: &str, size: &Size) -> usize {
+        let line = line.min(self.last_line());
+        let start = if below {
+            let idx = self.line_col_to_char(line, usize::MAX, true);
+            self.insert_text(idx, &format!("\n{indent}"));
+            idx + 1
+        } else {
+            let idx = self.rope.line_to_char(line);
+            self.insert_text(idx, &format!("{indent}\n"));
+            idx
+        };
+        self.set_cursor_char(start + indent.chars().count(), size);
+        start
+    }
+
+    /// Puts whole lines of `text` below or above the cursor line, the cursor goes to the first
+    /// non-blank char of the first one.
+    pub fn put_lines(&mut self, text: &str, below: bool, size: &Size) {
+        let (line, _) = self.line_col(self.cursor_char());
+        let first = line + usize::from(below);
+        if first <= self.last_line() || !below {
+            let idx = self.rope.line_to_char(first);
+            self.insert_text(idx, text);
+        } else if self.rope.chars().last() == Some('\n') {
+            let idx = self.rope.len_chars();
+            self.insert_text(idx, text);
+        } else {
+            // keep the last line without a newline
+            let idx = self.rope.len_chars();
+            let text = text.strip_suffix('\n').unwrap_or(text);
+            self.insert_text(idx, &format!("\n{text}"));
+        }
+        let idx = self.first_non_blank(first);
+        self.set_cursor_char(idx, size);
+    }
+
+    /// Joins `count` lines starting at `line` into one. The white space at the start of each
+    /// joined line becomes a single space, none after trailing white space or before `)`.
+    /// Returns `false` when there is no line to join.
+    pub fn join_lines(&mut self, line: usize, count: usize, size: &Size) -> bool {
+        let joins = count.saturating_sub(1).max(1);
+        if line >= self.last_line() {
+            return false;
+        }
+        let mut cursor = None;
+        for _ in 0..joins {
+            if line >= self.last_line() {
+                break;
+            }
+            let newline = self.line_col_to_char(line, usize::MAX, true);
+            let next = self.rope.line_to_char(line + 1);
+            let blanks = self
+                .rope
+                .chars_at(next)
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .count();
+            let trailing = newline > 0
+                && self.rope.line_to_char(line) < newline
+                && matches!(self.rope.char(newline - 1), ' ' | '\t');
+            let next_char = self.rope.get_char(next + blanks);
+            self.remove_text(newline..next + blanks);
+            let space = !trailing
+                && newline > self.rope.line_to_char(line)
+                && !matches!(next_char, None | Some('\n' | ')'));
+            if space {
+                self.insert_text(newline, " ");
+            }
+            cursor = Some(newline);
+        }
+        if let Some(idx) = cursor {
+            let idx = self.line_col_to_char(line, idx - self.rope.line_to_char(line), false);
+            self.set_cursor_char(idx, size);
+        }
+        true
+    }
+
+    /// Shifts lines `first` to `last` right by `width` spaces, or left by up to `width` columns
+    /// with `dedent`, where a tab counts as `width`. Empty lines are not indented.
+    pub fn shift_lines(&mut self, first: usize, last: usize, width: usize, dedent: bool) {
+        for line in first..=last.min(self.last_line()) {
+            let start = self.rope.line_to_char(line);
+            if dedent {
+                let mut cols = 0;
+                let remove = self
+                    .rope
+                    .chars_at(start)
+                    .take_while(|c| {
+                        let fits = cols < width && matches!(c, ' ' | '\t');
+                        cols += if *c == '\t' { width } else { 1 };
+                        fits
+                    })
+                    .count();
+                self.remove_text(start..start + remove);
+            } else if !matches!(self.rope.get_char(start), None | Some('\n')) {
+                self.insert_text(start, &" ".repeat(width));
+            }
+        }
+    }
+
+    /// Types `text` over the chars from the cursor on, like replace mode. Newlines and chars
+    /// past the end of the line are inserted. Returns the chars typed over, `None` for the
+    /// inserted ones.
+    pub fn overwrite(&mut self, text: &str, size: &Size) -> Vec<Option<char>> {
+        let mut idx = self.cursor_char();
+        let mut replaced = Vec::new();
+        for c in text.chars() {
+            let old = self
+                .rope
+                .get_char(idx)
+                .filter(|old| *old != '\n' && c != '\n');
+            if old.is_some() {
+                self.remove_text(idx..idx + 1);
+            }
+            self.insert_text(idx, c.encode_utf8(&mut [0; 4]));
+            replaced.push(old);
+            idx += 1;
+        }
+        self.set_cursor_char(idx, size);
+        replaced
+    }
+
+    /// Takes back the char before the cursor typed by `overwrite`, putting back the one it typed
+    /// over.
+    pub fn restore(&mut self, original: Option<char>, size: &Size) {
+        let idx = self.cursor_char();
+        if idx == 0 {
+            return;
+        }
+        self.remove_text(idx - 1..idx);
+        if let Some(c) = original {
+            self.insert_text(idx - 1, c.encode_utf8(&mut [0; 4]));
+        }
+        self.set_cursor_char(idx - 1, size);
+    }
+
+    /// Replaces `count` chars from the cursor with `c`, the cursor ends on the last of them.
+    /// A newline replaces them all with a single line break. Returns `false` and leaves the text
+    /// alone when the line has fewer chars left.
+    pub fn replace_chars(&mut self, c: char, count: usize, size: &Size) -> bool {
+        let idx = self.cursor_char();
+        let left = self
+            .rope
+            .chars_at(idx)
+            .take(count)
+            .take_while(|c| *c != '\n')
+            .count();
+        if count == 0 || left < count {
+            return false;
+        }
+        self.remove_text(idx..idx + count);
+        let cursor = if c == '\n' {
+            self.insert_text(idx, "\n");
+            idx + 1
+        } else {
+            self.insert_text(idx, &c.to_string().repeat(count));
+            idx + count - 1
+        };
+        self.set_cursor_char(cursor, size);
+        true
+    }
+
+    /// The word under or after the cursor on the cursor line.
+    pub fn word_at_cursor(&self) -> Option<String> {
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        let idx = self.cursor_char();
+        let row = self.rope.char_to_line(idx.min(self.rope.len_chars()));
+        let line_start = self.rope.line_to_char(row);
+        let line = self.rope.line(row).chars().collect::<Vec<_>>();
+        let col = idx - line_start;
+        let start = col + line.get(col..)?.iter().position(is_word)?;
+        let start = line[..start]
+            .iter()
+            .rposition(|c| !is_word(c))
+            .map_or(0, |idx| idx + 1);
+        let word = line[start..]
+            .iter()
+            .take_while(|c| is_word(c))
+            .collect::<String>();
+        Some(word)
     }
 
     pub fn backspace(&mut self) {
@@ -260,7 +718,7 @@ impl Buffer {
         let char_idx = self.rope.line_to_char(row);
         let start = (char_idx + col).saturating_sub(1);
         let end = char_idx + col;
-        self.rope.remove(start..end);
+        self.remove_text(start..end);
         if col == 0 {
             self.cursor_up();
             self.cursor_end();
@@ -275,7 +733,7 @@ impl Buffer {
         let char_idx = self.rope.line_to_char(row);
         let start = char_idx + col;
         let end = (char_idx + col).saturating_add(1);
-        self.rope.remove(start..end);
+        self.remove_text(start..end);
     }
 
     pub fn cursor_up(&mut self) -> bool {
@@ -328,7 +786,12 @@ impl Buffer {
     }
 
     pub fn cursor_home(&mut self) {
-        self.cursor.set_col(0);
+        self.cursor.set_col_effect(0);
+    }
+
+    pub fn set_row(&mut self, row: usize) {
+        self.cursor.set_row(row);
+        self.align_cursor();
     }
 
     pub fn scroll_up(&mut self) {
@@ -355,9 +818,14 @@ impl Buffer {
 impl Default for Buffer {
     fn default() -> Self {
         Self {
-            name: "N/A".into(),
+            name: NO_NAME.into(),
             rope: Rope::default(),
             cursor: Cursor::default(),
+            modified: false,
+            unswapped_changes: 0,
+            maps: ModeMaps::default(),
+            marks: Marks::default(),
+            disk: None,
         }
     }
 }
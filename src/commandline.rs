@@ -2,14 +2,37 @@
 use crate::AUTHOR;
 use clap::{crate_description, crate_name, crate_version, values_t, App, Arg};
 
-pub fn args() -> Vec<String> {
+#[derive(Debug)]
+pub struct Args {
+    pub files: Vec<String>,
+    pub recover: bool,
+    pub session: Option<String>,
+}
+
+pub fn args() -> Args {
     let matches = App::new(crate_name!())
         .version(crate_version!())
         .author(AUTHOR)
         .about(crate_description!())
         .arg(Arg::with_name("files").multiple(true))
+        .arg(
+            Arg::with_name("recover")
+                .short("r")
+                .help("List swap files, or recover the given files from their swap files"),
+        )
+        .arg(
+            Arg::with_name("session")
+                .short("S")
+                .takes_value(true)
+                .value_name("session")
+                .help("Open the files of a session written with :mksession"),
+        )
         .after_help("Pass in any number of files to ReVi to be placed in the Buffer list.")
         .get_matches();
 
-    values_t!(matches, "files", String).unwrap_or_default()
+    Args {
+        files: values_t!(matches, "files", String).unwrap_or_default(),
+        recover: matches.is_present("recover"),
+        session: matches.value_of("session").map(ToString::to_string),
+    }
 }
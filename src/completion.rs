@@ -0,0 +1,173 @@
+//! Completion of the word before the cursor on the command line.
+//! The first word completes to a command, the arguments depend on the command:
+//! files after `:e` and `:w`, buffer names after `:b` and option names after `:set`.
+use crate::options::Options;
+
+/// Candidates for the word being completed and which one is shown on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    /// Command line before the word being completed.
+    head: String,
+    /// The word as it was typed.
+    typed: String,
+    pub items: Vec<String>,
+    /// Candidate on the command line, `None` while the typed word is shown.
+    pub selected: Option<usize>,
+}
+
+impl Completion {
+    /// Completes the last word of `line`.
+    /// `commands` and `buffers` are the names that can be completed besides files and options.
+    pub fn new(line: &str, commands: &[String], buffers: &[String], fuzzy: bool) -> Self {
+        let line = line.trim_start();
+        let (head, typed) = match line.rfind(' ') {
+            Some(idx) => line.split_at(idx + 1),
+            None => ("", line),
+        };
+        let candidates = match head.split_whitespace().next() {
+            None => commands.to_vec(),
+            Some("e" | "edit" | "w" | "write" | "mks" | "mksession" | "mks!" | "mksession!") => {
+                files(typed)
+            }
+            Some("b" | "buffer") => buffers.to_vec(),
+            Some("set") => Options::NAMES.iter().map(ToString::to_string).collect(),
+            Some(_) => Vec::new(),
+        };
+        Self {
+            head: head.to_string(),
+            typed: typed.to_string(),
+            items: filter(candidates, typed, fuzzy),
+            selected: None,
+        }
+    }
+
+    /// Selects the next candidate, after the last one the typed word comes back.
+    pub fn next(&mut self) {
+        self.selected = match self.selected {
+            None if !self.items.is_empty() => Some(0),
+            Some(idx) if idx + 1 < self.items.len() => Some(idx + 1),
+            _ => None,
+        };
+    }
+
+    /// Selects the previous candidate, before the first one the typed word comes back.
+    pub fn prev(&mut self) {
+        self.selected = match self.selected {
+            None => self.items.len().checked_sub(1),
+            Some(0) => None,
+            Some(idx) => Some(idx - 1),
+        };
+    }
+
+    /// Column the completed word starts at.
+    pub fn start(&self) -> usize {
+        self.head.chars().count()
+    }
+
+    /// The command line with the selected candidate in place of the typed word.
+    pub fn line(&self) -> String {
+        let word = self
+            .selected
+            .and_then(|idx| self.items.get(idx))
+            .unwrap_or(&self.typed);
+        format!("{}{word}", self.head)
+    }
+}
+
+/// Entries of the directory `word` points into, directories end in `/`.
+/// Hidden entries only show up once the typed name starts with a dot.
+fn files(word: &str) -> Vec<String> {
+    let (dir, name) = match word.rfind('/') {
+        Some(idx) => word.split_at(idx + 1),
+        None => ("", word),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_str()?.to_string();
+            if file_name.starts_with('.') && !name.starts_with('.') {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{file_name}{slash}"))
+        })
+        .collect()
+}
+
+/// Candidates that match `word`, best matches first.
+/// Without `fuzzy` a candidate has to start with `word`.
+fn filter(candidates: Vec<String>, word: &str, fuzzy: bool) -> Vec<String> {
+    let mut scored = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let score = if fuzzy {
+                fuzzy_score(&candidate, word)?
+            } else {
+                candidate.starts_with(word).then_some(0)?
+            };
+            Some((score, candidate))
+        })
+        .collect::<Vec<_>>();
+    scored.sort();
+    scored.dedup();
+    scored.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// How far apart the characters of `word` are in `candidate`, lower is better.
+/// `None` when they do not all show up in order, case is ignored.
+fn fuzzy_score(candidate: &str, word: &str) -> Option<usize> {
+    let mut chars = candidate.chars().enumerate();
+    let mut score = 0;
+    let mut last = None;
+    for c in word.chars() {
+        let (idx, _) = chars.find(|(_, other)| other.eq_ignore_ascii_case(&c))?;
+        score += match last {
+            Some(last) => idx - last - 1,
+            None => idx,
+        };
+        last = Some(idx);
+    }
+    Some(score)
+}
+
+#[test]
+fn completes_by_position_on_the_line() {
+    let commands = vec!["edit".to_string(), "e".to_string(), "set".to_string()];
+    let buffers = vec!["src/main.rs".to_string(), "README.md".to_string()];
+    let completion = Completion::new("e", &commands, &buffers, false);
+    assert_eq!(completion.items, vec!["e", "edit"]);
+    let completion = Completion::new("b s", &commands, &buffers, false);
+    assert_eq!(completion.items, vec!["src/main.rs"]);
+    let completion = Completion::new("set whi", &commands, &buffers, false);
+    assert_eq!(completion.items, vec!["whichkey"]);
+    let completion = Completion::new("nmap x", &commands, &buffers, false);
+    assert!(completion.items.is_empty());
+}
+
+#[test]
+fn cycling_returns_to_typed_word() {
+    let commands = vec!["set".to_string(), "stop".to_string()];
+    let mut completion = Completion::new("s", &commands, &[], false);
+    completion.next();
+    assert_eq!(completion.line(), "set");
+    completion.next();
+    assert_eq!(completion.line(), "stop");
+    completion.next();
+    assert_eq!(completion.line(), "s");
+    completion.prev();
+    assert_eq!(completion.line(), "stop");
+}
+
+#[test]
+fn fuzzy_matches_in_order_best_first() {
+    assert_eq!(fuzzy_score("timeoutlen", "tmo"), Some(2));
+    assert_eq!(fuzzy_score("timeoutlen", "omt"), None);
+    let candidates = vec!["ttimeoutlen".to_string(), "timeoutlen".to_string()];
+    assert_eq!(
+        filter(candidates, "tlen", true),
+        vec!["timeoutlen", "ttimeoutlen"]
+    );
+}
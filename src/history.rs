@@ -0,0 +1,109 @@
+//! Lines entered on the command line, newest last.
+//! `<up>` and `<down>` only walk the entries that start with what was typed before browsing.
+use crate::state_file::now;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub line: String,
+    /// When the line was entered, in seconds since the epoch.
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    /// Entry shown on the command line while browsing.
+    index: Option<usize>,
+    /// The line as it was typed before browsing started.
+    typed: String,
+}
+
+impl History {
+    /// Adds `line` as the newest entry, an older copy of it is dropped.
+    /// Keeps at most `max` entries.
+    pub fn add(&mut self, line: &str, max: usize) {
+        self.reset();
+        if line.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry.line != line);
+        self.entries.push(Entry {
+            line: line.to_string(),
+            time: now(),
+        });
+        let extra = self.entries.len().saturating_sub(max);
+        self.entries.drain(..extra);
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Replaces the history with `entries`, oldest first.
+    pub fn set_entries(&mut self, entries: Vec<Entry>) {
+        self.reset();
+        self.entries = entries;
+    }
+
+    /// Stops browsing, the next `older` starts from the newest entry again.
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.typed.clear();
+    }
+
+    /// Older entry starting with the typed text, `line` is the command line when browsing starts.
+    pub fn older(&mut self, line: &str) -> Option<&str> {
+        if self.index.is_none() {
+            self.typed = line.to_string();
+        }
+        let end = self.index.unwrap_or(self.entries.len());
+        let idx = self.entries[..end]
+            .iter()
+            .rposition(|entry| entry.line.starts_with(&self.typed))?;
+        self.index = Some(idx);
+        Some(&self.entries[idx].line)
+    }
+
+    /// Newer entry starting with the typed text, past the newest one the typed text comes back.
+    pub fn newer(&mut self) -> Option<&str> {
+        let start = self.index? + 1;
+        let found = self.entries[start..]
+            .iter()
+            .position(|entry| entry.line.starts_with(&self.typed));
+        match found {
+            Some(offset) => {
+                self.index = Some(start + offset);
+                Some(&self.entries[start + offset].line)
+            }
+            None => {
+                self.index = None;
+                Some(&self.typed)
+            }
+        }
+    }
+}
+
+#[test]
+fn browsing_filters_by_typed_prefix() {
+    let mut history = History::default();
+    for line in ["set mouse", "w", "set whichkey", "e foo"] {
+        history.add(line, 10);
+    }
+    assert_eq!(history.older("set"), Some("set whichkey"));
+    assert_eq!(history.older("set whichkey"), Some("set mouse"));
+    assert_eq!(history.older("set mouse"), None);
+    assert_eq!(history.newer(), Some("set whichkey"));
+    assert_eq!(history.newer(), Some("set"));
+    assert_eq!(history.newer(), None);
+}
+
+#[test]
+fn adding_moves_duplicates_and_trims() {
+    let mut history = History::default();
+    for line in ["a", "b", "a", "c"] {
+        history.add(line, 2);
+    }
+    assert_eq!(history.older(""), Some("c"));
+    assert_eq!(history.older("c"), Some("a"));
+    assert_eq!(history.older("a"), None);
+}
@@ -9,11 +9,25 @@ Email: cowboy8625@protonmail.com
 mod api;
 mod buffer;
 mod commandline;
+mod completion;
+mod history;
 mod map_keys;
+mod marks;
 mod message;
+mod motion;
+mod options;
 mod parse_keys;
+mod paths;
+mod registers;
+mod session;
 mod state;
+mod state_file;
+mod swap;
+#[cfg(test)]
+mod tests;
+mod text_object;
 mod trie;
+mod watch;
 
 // use api::Rhai;
 use buffer::Buffer;
@@ -23,16 +37,26 @@ use state::State;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Insert,
     Command,
     Normal,
+    Visual,
+    /// After an operator like `d`, waiting for the motion it works on.
+    OperatorPending,
+    /// `R`, typed chars take the place of the ones under the cursor.
+    Replace,
 }
 
 #[derive(Debug)]
 pub struct Settings {
     pub buffers: Vec<Buffer>,
+    pub recover: bool,
+    /// Where history, registers and cursor positions are kept between sessions.
+    pub state_file: Option<std::path::PathBuf>,
+    /// Session to open, from `revi -S`.
+    pub session: Option<std::path::PathBuf>,
 }
 
 enum Revi {
@@ -48,12 +72,28 @@ impl App for Revi {
         let state = State::new(settings);
         let (state, mut rhai) = api::init(state).expect("failed to init rhai");
         rhai.eval_init();
+        let data = state.borrow_mut().session_data.take();
+        if let Some(data) = data {
+            if let Err(err) = rhai.session_load(data) {
+                state.borrow_mut().messages.push(hook_error(err));
+            }
+        }
         Self::Editor((state, rhai))
     }
 
     fn update(&mut self, message: Self::Message) -> Option<Self::Message> {
         match self {
-            Self::Editor((state, _)) => state.borrow_mut().update(message),
+            Self::Editor((state, rhai)) => {
+                // hooks run here, while they can borrow the state
+                let message = match message {
+                    Message::MakeSession(name, force, None) => match rhai.session_save() {
+                        Ok(data) => Message::MakeSession(name, force, data),
+                        Err(err) => Message::UserMessage(hook_error(err)),
+                    },
+                    message => message,
+                };
+                state.borrow_mut().update(message)
+            }
         }
     }
 
@@ -85,15 +125,41 @@ impl App for Revi {
             Self::Editor((state, _)) => state.borrow().quit(),
         }
     }
+
+    fn emergency_save(&mut self) {
+        match self {
+            Self::Editor((state, _)) => match state.try_borrow_mut() {
+                Ok(mut state) => state.emergency_save(),
+                Err(_) => eprintln!("revi: editor state is locked, unsaved buffers are lost"),
+            },
+        }
+    }
+}
+
+fn hook_error(err: String) -> message::UserMessageBuilder {
+    message::UserMessageBuilder::default()
+        .message(err)
+        .footer("init.rhai")
+        .fg(revi_ui::Color::Red)
 }
 
 fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let files = commandline::args();
-    let buffers = files
+    let args = commandline::args();
+    if args.recover && args.files.is_empty() {
+        swap::print_recoverable();
+        return Ok(());
+    }
+    let buffers = args
+        .files
         .iter()
         .map(|name| Buffer::from_path(name))
         .collect::<Vec<_>>();
-    let settings = Settings { buffers };
+    let settings = Settings {
+        buffers,
+        recover: args.recover,
+        state_file: Some(state_file::default_path()),
+        session: args.session.map(Into::into),
+    };
     Revi::new(settings).run()?;
     Ok(())
 }
@@ -1,14 +1,152 @@
 use super::{Message, Mode};
-use revi_ui::{string_to_keys, Keys};
+use crate::marks::Marks;
+use crate::motion::{Motion, Operator};
+use crate::text_object::TextObject;
+use revi_ui::{keys_to_string, string_to_keys, Keys};
 
-#[derive(Debug)]
+/// What a mapping does once its keys are typed.
+#[derive(Debug, Clone)]
+pub enum Mapping {
+    Message(Message),
+    /// Keys fed back through the key parser.
+    /// Without `remap` they only see the default mappings, like vim's `noremap`.
+    Keys {
+        keys: Vec<Keys>,
+        remap: bool,
+    },
+}
+
+impl From<Message> for Mapping {
+    fn from(message: Message) -> Self {
+        Self::Message(message)
+    }
+}
+
+impl From<Mapping> for Message {
+    fn from(mapping: Mapping) -> Self {
+        match mapping {
+            Mapping::Message(message) => message,
+            Mapping::Keys { keys, remap } => Message::FeedKeys(keys, remap),
+        }
+    }
+}
+
+impl std::fmt::Display for Mapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Message(message) => write!(f, "  {message:?}"),
+            Self::Keys { keys, remap } => {
+                let noremap = if *remap { ' ' } else { '*' };
+                write!(f, "{noremap} {}", keys_to_string(keys))
+            }
+        }
+    }
+}
+
+/// A mapping along with the text the which-key popup shows for it.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub mapping: Mapping,
+    pub desc: Option<String>,
+}
+
+impl Binding {
+    #[must_use]
+    pub fn with_desc(mut self, desc: impl Into<String>) -> Self {
+        self.desc = Some(desc.into());
+        self
+    }
+
+    /// The description, or one made up from what the mapping does.
+    #[must_use]
+    pub fn description(&self) -> String {
+        if let Some(desc) = &self.desc {
+            return desc.clone();
+        }
+        match &self.mapping {
+            Mapping::Message(message) => describe(message),
+            Mapping::Keys { keys, .. } => keys_to_string(keys),
+        }
+    }
+}
+
+impl From<Mapping> for Binding {
+    fn from(mapping: Mapping) -> Self {
+        Self {
+            mapping,
+            desc: None,
+        }
+    }
+}
+
+/// `ChangeMode(Insert)` reads as `change mode insert`.
+fn describe(message: &Message) -> String {
+    let name = format!("{message:?}");
+    let mut desc = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && !desc.is_empty() && !desc.ends_with(' ') {
+            desc.push(' ');
+        }
+        match c {
+            '(' | ')' | '"' => {
+                if !desc.ends_with(' ') {
+                    desc.push(' ');
+                }
+            }
+            _ => desc.push(c.to_ascii_lowercase()),
+        }
+    }
+    desc.trim_end().to_string()
+}
+
+#[derive(Debug, Clone)]
 enum MapNode {
     Map(Keys, KeyMap),
-    Middle(Keys, KeyMap, Message),
-    End(Keys, Message),
+    Middle(Keys, KeyMap, Binding),
+    End(Keys, Binding),
 }
 
-#[derive(Debug)]
+impl MapNode {
+    fn key(&self) -> &Keys {
+        match self {
+            Self::Map(key, _) | Self::Middle(key, _, _) | Self::End(key, _) => key,
+        }
+    }
+
+    fn children(&self) -> Option<&KeyMap> {
+        match self {
+            Self::Map(_, map) | Self::Middle(_, map, _) => Some(map),
+            Self::End(..) => None,
+        }
+    }
+
+    fn binding(&self) -> Option<&Binding> {
+        match self {
+            Self::Middle(_, _, binding) | Self::End(_, binding) => Some(binding),
+            Self::Map(..) => None,
+        }
+    }
+
+    /// Number of mappings reachable from this node, itself included.
+    fn count(&self) -> usize {
+        let own = usize::from(self.binding().is_some());
+        let children = self
+            .children()
+            .map_or(0, |map| map.mappings.iter().map(MapNode::count).sum());
+        own + children
+    }
+
+    /// What the which-key popup shows for this node, `+` marks a prefix of more mappings.
+    fn description(&self) -> String {
+        match self {
+            Self::Map(..) => format!("+{} mappings", self.count()),
+            Self::Middle(_, _, binding) => format!("{} +", binding.description()),
+            Self::End(_, binding) => binding.description(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 struct KeyMap {
     mappings: Vec<MapNode>,
 }
@@ -26,62 +164,75 @@ impl KeyMap {
         }
     }
 
-    fn is_possible_command(&self, keys: &[Keys]) -> bool {
-        if keys.is_empty() {
-            return false;
+    /// Node reached by following `keys` from this map.
+    fn find(&self, keys: &[Keys]) -> Option<&MapNode> {
+        let (first, rest) = keys.split_first()?;
+        let node = self.mappings.iter().find(|node| node.key() == first)?;
+        if rest.is_empty() {
+            return Some(node);
         }
-        self.mappings.iter().any(|node| match node {
-            MapNode::Map(key, _) if key == &keys[0] && keys.len() == 1 => true,
-            MapNode::Map(key, keymap) if key == &keys[0] => keymap.is_command(&keys[1..]),
-            MapNode::Middle(key, _, _) if key == &keys[0] && keys.len() == 1 => true,
-            MapNode::Middle(key, keymap, _) if key == &keys[0] => keymap.is_command(&keys[1..]),
-            MapNode::End(key, _) if key == &keys[0] => true,
-            _ => false,
-        })
+        node.children()?.find(rest)
     }
 
-    fn is_command(&self, keys: &[Keys]) -> bool {
-        if keys.is_empty() {
-            return false;
-        }
-        self.mappings.iter().any(|node| match node {
-            MapNode::Map(key, keymap) if key == &keys[0] => keymap.is_command(&keys[1..]),
-            MapNode::Middle(key, _, _) if key == &keys[0] && keys.len() == 1 => true,
-            MapNode::Middle(key, keymap, _) if key == &keys[0] => keymap.is_command(&keys[1..]),
-            MapNode::End(key, _) if key == &keys[0] => true,
-            _ => false,
+    /// Mapping for exactly `keys`.
+    fn get(&self, keys: &[Keys]) -> Option<Mapping> {
+        self.find(keys)
+            .and_then(MapNode::binding)
+            .map(|binding| binding.mapping.clone())
+    }
+
+    /// Keys that can follow `keys` with their descriptions.
+    fn continuations(&self, keys: &[Keys]) -> Vec<(Keys, String)> {
+        let map = if keys.is_empty() {
+            Some(self)
+        } else {
+            self.find(keys).and_then(MapNode::children)
+        };
+        map.map(|map| {
+            map.mappings
+                .iter()
+                .map(|node| (*node.key(), node.description()))
+                .collect()
         })
+        .unwrap_or_default()
     }
 
-    // fn _is_last_branch_command(&self, _: &[Keys]) -> bool {
-    //     todo!()
-    // }
+    /// `keys` are the start of a longer mapping.
+    fn has_longer(&self, keys: &[Keys]) -> bool {
+        self.find(keys)
+            .and_then(MapNode::children)
+            .is_some_and(|map| !map.mappings.is_empty())
+    }
 
-    fn get(&self, key: &[Keys]) -> Option<Message> {
-        if key.is_empty() {
-            return None;
-        }
+    /// Every mapping in this map, `prefix` is prepended to the keys.
+    fn list(&self, prefix: &[Keys], list: &mut Vec<(Vec<Keys>, Mapping)>) {
         for node in self.mappings.iter() {
-            match node {
-                MapNode::Map(k, map) if k == &key[0] => return map.get(&key[1..]),
-                MapNode::Middle(k, _, cmd) if k == &key[0] && key.len() == 1 => {
-                    return Some(cmd.clone())
-                }
-                MapNode::Middle(k, map, _) if k == &key[0] => return map.get(&key[1..]),
-                MapNode::End(k, command) if k == &key[0] => return Some(command.clone()),
-                _ => {}
-            };
+            let mut keys = prefix.to_vec();
+            keys.push(*node.key());
+            if let Some(binding) = node.binding() {
+                list.push((keys.clone(), binding.mapping.clone()));
+            }
+            if let Some(map) = node.children() {
+                map.list(&keys, list);
+            }
         }
-        None
     }
 
-    fn insert(&mut self, keys: &[Keys], command: Message) {
+    fn insert(&mut self, keys: &[Keys], command: Binding) {
         let Some(key) = keys.first() else {
             return;
         };
         for node in self.mappings.iter_mut() {
             match node {
+                MapNode::Map(k, map) if k == key && keys.len() == 1 => {
+                    *node = MapNode::Middle(*k, std::mem::take(map), command);
+                    return;
+                }
                 MapNode::Map(k, map) if k == key => return map.insert(&keys[1..], command),
+                MapNode::Middle(k, _, cmd) if k == key && keys.len() == 1 => {
+                    *cmd = command;
+                    return;
+                }
                 MapNode::Middle(k, map, _) if k == key => return map.insert(&keys[1..], command),
                 MapNode::End(k, cmd) if k == key && keys.len() == 1 => {
                     *cmd = command;
@@ -100,7 +251,7 @@ impl KeyMap {
     }
 
     // Blindly inserts new mapping
-    fn insert_new(&mut self, keys: &[Keys], command: Message) {
+    fn insert_new(&mut self, keys: &[Keys], command: Binding) {
         let mut key_iter = keys.iter().rev();
         let Some(key) = key_iter.next() else {
             return;
@@ -111,95 +262,220 @@ impl KeyMap {
         });
         self.mappings.push(mapnode);
     }
+
+    /// Removes the mapping for exactly `keys`, longer mappings starting with `keys` stay.
+    /// Returns `false` when there was nothing to remove.
+    fn remove(&mut self, keys: &[Keys]) -> bool {
+        let Some((first, rest)) = keys.split_first() else {
+            return false;
+        };
+        let Some(idx) = self.mappings.iter().position(|node| node.key() == first) else {
+            return false;
+        };
+        let node = &mut self.mappings[idx];
+        let removed = match node {
+            MapNode::End(..) if rest.is_empty() => true,
+            MapNode::Middle(k, map, _) if rest.is_empty() => {
+                *node = MapNode::Map(*k, std::mem::take(map));
+                true
+            }
+            MapNode::Map(_, map) | MapNode::Middle(_, map, _) => map.remove(rest),
+            _ => false,
+        };
+        // Drop branches that no longer lead to a mapping.
+        let empty = match &self.mappings[idx] {
+            MapNode::End(..) => removed,
+            MapNode::Map(_, map) => map.mappings.is_empty(),
+            MapNode::Middle(..) => false,
+        };
+        if empty {
+            self.mappings.remove(idx);
+        }
+        removed
+    }
 }
 
-#[derive(Debug)]
-pub struct Mapper {
+/// One key map per mode.
+#[derive(Debug, Clone, Default)]
+pub struct ModeMaps {
     nmaps: KeyMap,
     imaps: KeyMap,
     cmaps: KeyMap,
+    vmaps: KeyMap,
+    omaps: KeyMap,
+}
+
+impl ModeMaps {
+    fn get(&self, mode: &Mode) -> &KeyMap {
+        match mode {
+            Mode::Normal => &self.nmaps,
+            // replace mode types like insert mode and shares its mappings
+            Mode::Insert | Mode::Replace => &self.imaps,
+            Mode::Command => &self.cmaps,
+            Mode::Visual => &self.vmaps,
+            Mode::OperatorPending => &self.omaps,
+        }
+    }
+
+    fn get_mut(&mut self, mode: Mode) -> &mut KeyMap {
+        match mode {
+            Mode::Normal => &mut self.nmaps,
+            Mode::Insert | Mode::Replace => &mut self.imaps,
+            Mode::Command => &mut self.cmaps,
+            Mode::Visual => &mut self.vmaps,
+            Mode::OperatorPending => &mut self.omaps,
+        }
+    }
+
+    pub fn insert(&mut self, mode: Mode, keys: &[Keys], binding: impl Into<Binding>) {
+        self.get_mut(mode).insert(keys, binding.into());
+    }
+
+    pub fn remove(&mut self, mode: Mode, keys: &[Keys]) -> bool {
+        self.get_mut(mode).remove(keys)
+    }
+
+    /// Mappings of `mode` whose keys start with `prefix`.
+    #[must_use]
+    pub fn list(&self, mode: &Mode, prefix: &[Keys]) -> Vec<(Vec<Keys>, Mapping)> {
+        let mut list = Vec::new();
+        self.get(mode).list(&[], &mut list);
+        list.retain(|(keys, _)| keys.starts_with(prefix));
+        list
+    }
+}
+
+/// Which mode a `:map` style command works on and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapCommand {
+    pub mode: Mode,
+    pub remap: bool,
+    pub unmap: bool,
+}
+
+impl MapCommand {
+    /// Names of every map command, used for completion.
+    pub const NAMES: &'static [&'static str] = &[
+        "map", "nmap", "noremap", "nnoremap", "imap", "inoremap", "cmap", "cnoremap", "vmap",
+        "vnoremap", "omap", "onoremap", "unmap", "nunmap", "iunmap", "cunmap", "vunmap", "ounmap",
+    ];
+
+    #[must_use]
+    pub fn parse(cmd: &str) -> Option<Self> {
+        let (mode, remap, unmap) = match cmd {
+            "map" | "nmap" | "nm" => (Mode::Normal, true, false),
+            "noremap" | "no" | "nnoremap" | "nn" => (Mode::Normal, false, false),
+            "imap" | "im" => (Mode::Insert, true, false),
+            "inoremap" | "ino" => (Mode::Insert, false, false),
+            "cmap" | "cm" => (Mode::Command, true, false),
+            "cnoremap" | "cno" => (Mode::Command, false, false),
+            "vmap" | "vm" => (Mode::Visual, true, false),
+            "vnoremap" | "vn" => (Mode::Visual, false, false),
+            "omap" | "om" => (Mode::OperatorPending, true, false),
+            "onoremap" | "ono" => (Mode::OperatorPending, false, false),
+            "unmap" | "unm" | "nunmap" | "nun" => (Mode::Normal, true, true),
+            "iunmap" | "iu" => (Mode::Insert, true, true),
+            "cunmap" | "cu" => (Mode::Command, true, true),
+            "vunmap" | "vu" => (Mode::Visual, true, true),
+            "ounmap" | "ou" => (Mode::OperatorPending, true, true),
+            _ => return None,
+        };
+        Some(Self { mode, remap, unmap })
+    }
+}
+
+/// Default mappings with the user's mappings layered on top.
+#[derive(Debug)]
+pub struct Mapper {
+    defaults: ModeMaps,
+    pub user: ModeMaps,
 }
 
 impl Default for Mapper {
     fn default() -> Self {
-        Self::new().build_normal().build_insert().build_command()
+        Self::new()
+            .build_normal()
+            .build_insert()
+            .build_command()
+            .build_visual()
+            .build_operator_pending()
     }
 }
 
 impl Mapper {
     fn new() -> Self {
         Self {
-            nmaps: KeyMap::new(),
-            imaps: KeyMap::new(),
-            cmaps: KeyMap::new(),
-        }
-    }
-
-    fn get_map(&self, mode: &Mode) -> &KeyMap {
-        match mode {
-            Mode::Normal => &self.nmaps,
-            Mode::Insert => &self.imaps,
-            Mode::Command => &self.cmaps,
+            defaults: ModeMaps::default(),
+            user: ModeMaps::default(),
         }
     }
 
-    fn get_map_mut(&mut self, mode: Mode) -> &mut KeyMap {
-        match mode {
-            Mode::Normal => &mut self.nmaps,
-            Mode::Insert => &mut self.imaps,
-            Mode::Command => &mut self.cmaps,
+    /// Maps searched for keys, most specific first.
+    /// Keys of a `noremap` mapping only see the defaults.
+    fn layers<'a>(&'a self, local: &'a ModeMaps, remap: bool) -> Vec<&'a ModeMaps> {
+        if remap {
+            vec![local, &self.user, &self.defaults]
+        } else {
+            vec![&self.defaults]
         }
     }
 
+    /// `keys` are the start of a mapping longer than `keys`.
     #[must_use]
-    pub fn _is_mapping(&self, mode: &Mode, keys: &[Keys]) -> bool {
-        self.get_map(mode).is_command(keys)
+    pub fn has_longer_mapping(
+        &self,
+        mode: &Mode,
+        keys: &[Keys],
+        local: &ModeMaps,
+        remap: bool,
+    ) -> bool {
+        self.layers(local, remap)
+            .into_iter()
+            .any(|maps| maps.get(mode).has_longer(keys))
     }
 
+    /// Mapping for exactly `keys`, buffer local mappings win over global ones.
     #[must_use]
-    pub fn is_possible_mapping(&self, mode: &Mode, keys: &[Keys]) -> bool {
-        self.get_map(mode).is_possible_command(keys)
+    pub fn get_mapping(
+        &self,
+        mode: &Mode,
+        keys: &[Keys],
+        local: &ModeMaps,
+        remap: bool,
+    ) -> Option<Mapping> {
+        self.layers(local, remap)
+            .into_iter()
+            .find_map(|maps| maps.get(mode).get(keys))
     }
 
+    /// Keys that can follow `keys`, with descriptions, for the which-key popup.
     #[must_use]
-    pub fn get_mapping(&self, mode: &Mode, keys: &[Keys]) -> Option<Message> {
-        self.get_map(mode).get(keys)
+    pub fn continuations(
+        &self,
+        mode: &Mode,
+        keys: &[Keys],
+        local: &ModeMaps,
+        remap: bool,
+    ) -> Vec<(Keys, String)> {
+        let mut list: Vec<(Keys, String)> = Vec::new();
+        for maps in self.layers(local, remap) {
+            for (key, desc) in maps.get(mode).continuations(keys) {
+                // The layer that wins the lookup also wins the description.
+                if !list.iter().any(|(k, _)| *k == key) {
+                    list.push((key, desc));
+                }
+            }
+        }
+        list
     }
 
     #[must_use]
     pub fn with_mapping(mut self, mode: Mode, keys: &str, message: Message) -> Self {
-        self.get_map_mut(mode)
-            .insert(&string_to_keys(keys), message);
+        self.defaults
+            .insert(mode, &string_to_keys(keys), Mapping::from(message));
         self
     }
 
-    pub fn _nmap(&mut self, keys: &str, message: Message) {
-        self.nmaps.insert(&string_to_keys(keys), message);
-    }
-
-    // pub fn nmap_from_str(&mut self, keys: &str, command: &str) {
-    //     let keys = string_to_keys(keys);
-    //     let command = string_to_keys(command);
-    //     let mut cmds = Vec::new();
-    //     let mut combo = Vec::new();
-    //     for key in command.iter() {
-    //         match self.get_mapping(&Mode::Normal, &[*key]) {
-    //             Some(cmd) => cmds.push(cmd),
-    //             None => match self.get_mapping(&Mode::Normal, &combo) {
-    //                 Some(cmd) => {
-    //                     cmds.push(cmd);
-    //                     combo.clear();
-    //                 }
-    //                 _ => combo.push(*key),
-    //             },
-    //         }
-    //     }
-    //     if cmds.is_empty() {
-    //         return;
-    //     }
-    //     self.nmaps.insert(&keys, ExeCommandList(cmds).into());
-    // }
-
     fn build_normal(self) -> Self {
         self.with_mapping(Mode::Normal, "j", Message::CursorDown)
             .with_mapping(Mode::Normal, "<down>", Message::CursorDown)
@@ -211,30 +487,42 @@ impl Mapper {
             .with_mapping(Mode::Normal, "<right>", Message::CursorRight)
             .with_mapping(Mode::Normal, ":", Message::ChangeMode(Mode::Command))
             .with_mapping(Mode::Normal, "i", Message::ChangeMode(Mode::Insert))
+            .with_mapping(Mode::Normal, "R", Message::ChangeMode(Mode::Replace))
+            .with_mapping(Mode::Normal, "r", Message::ReplaceChar)
+            // every char takes one column on screen, so virtual replace is the same as `r`
+            .with_mapping(Mode::Normal, "gr", Message::ReplaceChar)
+            .with_mapping(Mode::Normal, "v", Message::ChangeMode(Mode::Visual))
             .with_mapping(Mode::Normal, "x", Message::Delete)
             .with_mapping(Mode::Normal, "<delete>", Message::Delete)
-            // .with_mapping(Mode::Normal, "dd", DeleteLine)
             .with_mapping(Mode::Normal, "<home>", Message::CursorHome)
             .with_mapping(Mode::Normal, "0", Message::CursorHome)
-            // .with_mapping(Mode::Normal, "gg", CursorTopOfBuffer)
-            // .with_mapping(Mode::Normal, "G", CursorToBottomOfBuffer)
+            .with_mapping(Mode::Normal, "gg", Message::CursorTopOfBuffer)
+            .with_mapping(Mode::Normal, "G", Message::CursorBottomOfBuffer)
+            .with_mapping(Mode::Normal, "<C-o>", Message::JumpOlder)
+            .with_mapping(Mode::Normal, "<C-i>", Message::JumpNewer)
+            .with_mapping(Mode::Normal, "<tab>", Message::JumpNewer)
+            .with_mapping(Mode::Normal, "g;", Message::ChangeOlder)
+            .with_mapping(Mode::Normal, "g,", Message::ChangeNewer)
             .with_mapping(Mode::Normal, "<end>", Message::CursorEnd)
             .with_mapping(Mode::Normal, "$", Message::CursorEnd)
             .with_mapping(Mode::Normal, "A", Message::InsertAtEnd)
+            .with_mapping(Mode::Normal, "<C-z>", Message::Suspend)
+            .with_mapping(Mode::Normal, ".", Message::RepeatChange)
+            .with_mapping(Mode::Normal, "o", Message::OpenLine(true))
+            .with_mapping(Mode::Normal, "O", Message::OpenLine(false))
+            .with_mapping(Mode::Normal, "J", Message::JoinLines)
+            .with_mapping(Mode::Normal, "p", Message::Put(true))
+            .with_mapping(Mode::Normal, "P", Message::Put(false))
+            .with_mapping(Mode::Normal, "S", Message::LineOperator(Operator::Change))
+            .with_mapping(Mode::Normal, "Y", Message::LineOperator(Operator::Yank))
+            .with_operators(Mode::Normal)
+            .with_motions(Mode::Normal)
+            .with_marks()
+            .with_macros()
         // .with_mapping(Mode::Normal, "<C-y>", ScrollUp, CursorDown)
         // .with_mapping(Mode::Normal, "<C-e>", ScrollDown, CursorUp)
         // .with_mapping(Mode::Normal, "<C-u>", ScrollUp)
         // .with_mapping(Mode::Normal, "<C-d>", ScrollDown)
-        //     .with_mapping(
-        //         Mode::Normal,
-        //         "o",
-        //         End, ChangeMode(Mode::Insert), CursorRight, NewLine,
-        //     )
-        //     .with_mapping(
-        //         Mode::Normal,
-        //         "O",
-        //         CursorHome, NewLine, ChangeMode(Mode::Insert), CursorUp,
-        //     )
         //     .with_mapping(Mode::Normal, "^", FirstCharInLine)
         //     .with_mapping(
         //         Mode::Normal,
@@ -249,13 +537,138 @@ impl Mapper {
         //         "<enter>",
         //         ExecuteCommandLine, ExitCommandMode,
         //     )
-        //     .with_mapping(Mode::Normal, "yy", YankLine)
-        //     .with_mapping(Mode::Normal, "p", Paste)
-        //     .with_mapping(Mode::Normal, "P", PasteBack)
         //     .with_mapping(Mode::Normal, "u", Undo)
         //     .with_mapping(Mode::Normal, "<space>a", CursorRight)
     }
 
+    /// Word, sentence, paragraph, find and bracket motions, which take a count and work after
+    /// operators.
+    fn with_motions(self, mode: Mode) -> Self {
+        [
+            ("w", Motion::WordForward(false)),
+            ("W", Motion::WordForward(true)),
+            ("b", Motion::WordBackward(false)),
+            ("B", Motion::WordBackward(true)),
+            ("e", Motion::WordEnd(false)),
+            ("E", Motion::WordEnd(true)),
+            ("ge", Motion::WordEndBackward(false)),
+            ("gE", Motion::WordEndBackward(true)),
+            ("}", Motion::ParagraphForward),
+            ("{", Motion::ParagraphBackward),
+            (")", Motion::SentenceForward),
+            ("(", Motion::SentenceBackward),
+        ]
+        .into_iter()
+        .fold(self, |maps, (keys, motion)| {
+            maps.with_mapping(mode, keys, Message::Motion(motion))
+        })
+        .with_mapping(mode, "%", Message::Motion(Motion::MatchBracket))
+        .with_mapping(mode, "f", Message::FindChar(true, false))
+        .with_mapping(mode, "t", Message::FindChar(true, true))
+        .with_mapping(mode, "F", Message::FindChar(false, false))
+        .with_mapping(mode, "T", Message::FindChar(false, true))
+        .with_mapping(mode, ";", Message::RepeatFind(false))
+        .with_mapping(mode, ",", Message::RepeatFind(true))
+    }
+
+    /// `i{object}` and `a{object}` pick the inner and around variant of a text object.
+    fn with_text_objects(self, mode: Mode) -> Self {
+        let brackets = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+        let bracket_keys = brackets.into_iter().flat_map(|(open, close)| {
+            let object = TextObject::Bracket(open, close);
+            let alias = match open {
+                '(' => Some("b"),
+                '{' => Some("B"),
+                _ => None,
+            };
+            // `<` starts key notation, `<lt>` is the key itself
+            let open = if open == '<' {
+                "<lt>".to_string()
+            } else {
+                open.to_string()
+            };
+            [Some(open), Some(close.to_string()), alias.map(String::from)]
+                .into_iter()
+                .flatten()
+                .map(move |keys| (keys, object))
+        });
+        [
+            ("w", TextObject::Word(false)),
+            ("W", TextObject::Word(true)),
+            ("s", TextObject::Sentence),
+            ("p", TextObject::Paragraph),
+            ("\"", TextObject::Quote('"')),
+            ("'", TextObject::Quote('\'')),
+            ("`", TextObject::Quote('`')),
+            ("t", TextObject::Tag),
+        ]
+        .into_iter()
+        .map(|(keys, object)| (keys.to_string(), object))
+        .chain(bracket_keys)
+        .fold(self, |maps, (keys, object)| {
+            maps.with_mapping(mode, &format!("i{keys}"), Message::TextObject(object, true))
+                .with_mapping(
+                    mode,
+                    &format!("a{keys}"),
+                    Message::TextObject(object, false),
+                )
+        })
+    }
+
+    /// `d`, `c`, `y`, `>` and `<` wait for a motion in normal mode and work on the selection in
+    /// visual mode. Typed again while waiting, like `dd`, they work on whole lines.
+    fn with_operators(self, mode: Mode) -> Self {
+        self.with_mapping(mode, "d", Message::Operator(Operator::Delete))
+            .with_mapping(mode, "c", Message::Operator(Operator::Change))
+            .with_mapping(mode, "y", Message::Operator(Operator::Yank))
+            .with_mapping(mode, ">", Message::Operator(Operator::Indent))
+            .with_mapping(mode, "<lt>", Message::Operator(Operator::Dedent))
+    }
+
+    /// `m{a-zA-Z}` sets a mark, `` `{mark} `` and `'{mark}` jump to it.
+    fn with_marks(self) -> Self {
+        let names = ('a'..='z')
+            .chain('A'..='Z')
+            .chain(['`', '\'', '.', '^', '[', ']']);
+        names.fold(self, |maps, name| {
+            let maps = if Marks::can_set(name) {
+                maps.with_mapping(Mode::Normal, &format!("m{name}"), Message::SetMark(name))
+            } else {
+                maps
+            };
+            maps.with_mapping(
+                Mode::Normal,
+                &format!("`{name}"),
+                Message::GoToMark(name, true),
+            )
+            .with_mapping(
+                Mode::Normal,
+                &format!("'{name}"),
+                Message::GoToMark(name, false),
+            )
+        })
+    }
+
+    /// `q{a-zA-Z}` records a macro, `@{a-zA-Z}` plays it and `@@` plays the last one again.
+    fn with_macros(self) -> Self {
+        let names = ('a'..='z').chain('A'..='Z');
+        names
+            .fold(self, |maps, name| {
+                maps.with_mapping(
+                    Mode::Normal,
+                    &format!("q{name}"),
+                    Message::RecordMacro(name),
+                )
+                .with_mapping(
+                    Mode::Normal,
+                    &format!("@{name}"),
+                    Message::PlayMacro(name),
+                )
+            })
+            .with_mapping(Mode::Normal, "@@", Message::PlayMacro('@'))
+            .with_mapping(Mode::Normal, "@:", Message::PlayMacro(':'))
+    }
+
     fn build_insert(self) -> Self {
         self.with_mapping(Mode::Insert, "<esc>", Message::ChangeMode(Mode::Normal))
             .with_mapping(Mode::Insert, "<backspace>", Message::BackSpace)
@@ -268,13 +681,65 @@ impl Mapper {
         //     .with_mapping(Mode::Insert, "<tab>", InsertTab)
     }
 
+    fn build_visual(self) -> Self {
+        self.with_mapping(Mode::Visual, "<esc>", Message::ChangeMode(Mode::Normal))
+            .with_mapping(Mode::Visual, "v", Message::ChangeMode(Mode::Normal))
+            .with_mapping(Mode::Visual, "j", Message::CursorDown)
+            .with_mapping(Mode::Visual, "<down>", Message::CursorDown)
+            .with_mapping(Mode::Visual, "k", Message::CursorUp)
+            .with_mapping(Mode::Visual, "<up>", Message::CursorUp)
+            .with_mapping(Mode::Visual, "h", Message::CursorLeft)
+            .with_mapping(Mode::Visual, "<left>", Message::CursorLeft)
+            .with_mapping(Mode::Visual, "l", Message::CursorRight)
+            .with_mapping(Mode::Visual, "<right>", Message::CursorRight)
+            .with_mapping(Mode::Visual, "0", Message::CursorHome)
+            .with_mapping(Mode::Visual, "$", Message::CursorEnd)
+            .with_operators(Mode::Visual)
+            .with_motions(Mode::Visual)
+            .with_text_objects(Mode::Visual)
+    }
+
+    fn build_operator_pending(self) -> Self {
+        let mode = Mode::OperatorPending;
+        self.with_mapping(mode, "<esc>", Message::ChangeMode(Mode::Normal))
+            .with_mapping(mode, "h", Message::Motion(Motion::Left))
+            .with_mapping(mode, "<left>", Message::Motion(Motion::Left))
+            .with_mapping(mode, "l", Message::Motion(Motion::Right))
+            .with_mapping(mode, "<right>", Message::Motion(Motion::Right))
+            .with_mapping(mode, "k", Message::Motion(Motion::Up))
+            .with_mapping(mode, "<up>", Message::Motion(Motion::Up))
+            .with_mapping(mode, "j", Message::Motion(Motion::Down))
+            .with_mapping(mode, "<down>", Message::Motion(Motion::Down))
+            .with_mapping(mode, "0", Message::Motion(Motion::LineStart))
+            .with_mapping(mode, "<home>", Message::Motion(Motion::LineStart))
+            .with_mapping(mode, "$", Message::Motion(Motion::LineEnd))
+            .with_mapping(mode, "<end>", Message::Motion(Motion::LineEnd))
+            .with_mapping(mode, "gg", Message::Motion(Motion::FirstLine))
+            .with_mapping(mode, "G", Message::Motion(Motion::LastLine))
+            .with_operators(mode)
+            .with_motions(mode)
+            .with_text_objects(mode)
+    }
+
     fn build_command(self) -> Self {
         self.with_mapping(Mode::Command, "<esc>", Message::ChangeMode(Mode::Normal))
             .with_mapping(Mode::Command, "<enter>", Message::ExecuteCommand)
             .with_mapping(Mode::Command, "<backspace>", Message::BackSpace)
             .with_mapping(Mode::Command, "<tab>", Message::NextAvailableCommand)
-        // .with_mapping(Mode::Command, "<c-h>", CursorLeft)
-        // .with_mapping(Mode::Command, "<c-l>", CursorRight)
+            .with_mapping(Mode::Command, "<S-Tab>", Message::PrevAvailableCommand)
+            .with_mapping(Mode::Command, "<left>", Message::CursorLeft)
+            .with_mapping(Mode::Command, "<right>", Message::CursorRight)
+            .with_mapping(Mode::Command, "<c-h>", Message::CursorLeft)
+            .with_mapping(Mode::Command, "<c-l>", Message::CursorRight)
+            .with_mapping(Mode::Command, "<home>", Message::CursorHome)
+            .with_mapping(Mode::Command, "<end>", Message::CursorEnd)
+            .with_mapping(Mode::Command, "<C-a>", Message::CursorHome)
+            .with_mapping(Mode::Command, "<C-e>", Message::CursorEnd)
+            .with_mapping(Mode::Command, "<C-w>", Message::DeleteWordBefore)
+            .with_mapping(Mode::Command, "<C-u>", Message::DeleteToLineStart)
+            .with_mapping(Mode::Command, "<up>", Message::HistoryUp)
+            .with_mapping(Mode::Command, "<down>", Message::HistoryDown)
+            .with_mapping(Mode::Command, "<C-r>", Message::InsertRegister)
     }
 }
 
@@ -309,3 +774,18 @@ impl Mapper {
 //         assert_eq!(left, right);
 //     }
 // }
+
+#[test]
+fn continuations_describe_next_keys() {
+    let mapper = Mapper::new()
+        .with_mapping(Mode::Normal, "g", Message::ChangeMode(Mode::Insert))
+        .with_mapping(Mode::Normal, "gh", Message::CursorHome)
+        .with_mapping(Mode::Normal, "gjk", Message::CursorEnd);
+    let local = ModeMaps::default();
+    let root = mapper.continuations(&Mode::Normal, &[], &local, true);
+    assert_eq!(root.len(), 1);
+    assert_eq!(root[0].1, "change mode insert +");
+    let next = mapper.continuations(&Mode::Normal, &string_to_keys("g"), &local, true);
+    let descs = next.into_iter().map(|(_, desc)| desc).collect::<Vec<_>>();
+    assert_eq!(descs, vec!["cursor home", "+1 mappings"]);
+}
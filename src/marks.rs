@@ -0,0 +1,147 @@
+//! Marks remember char indices in a buffer, like vim's `ma` and `` `a ``.
+//! They move along when text before them is inserted or deleted, and so do the buffer's
+//! entries in the jumplist and the change list.
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Position before the latest jump, `''` and ``` `` ``` go back to it.
+pub const CONTEXT: char = '`';
+/// Where the last change was made.
+pub const LAST_CHANGE: char = '.';
+/// Where insert mode was last left.
+pub const LAST_INSERT: char = '^';
+/// First and last char of the last change.
+pub const CHANGE_START: char = '[';
+pub const CHANGE_END: char = ']';
+/// Entries kept in the jumplist and the change list of a buffer.
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+    named: BTreeMap<char, usize>,
+    /// Positions jumped away from, with a sequence number that orders them across buffers.
+    jumps: Vec<(u64, usize)>,
+    /// Positions of changes, oldest first.
+    changes: Vec<usize>,
+    /// Entry of `changes` that `g;` and `g,` last went to, `None` after a new change.
+    pub change_idx: Option<usize>,
+}
+
+impl Marks {
+    /// Marks `m` can set, uppercase marks are global file marks.
+    pub fn can_set(name: char) -> bool {
+        name.is_ascii_alphabetic() || matches!(name, CONTEXT | '\'' | CHANGE_START | CHANGE_END)
+    }
+
+    pub fn get(&self, name: char) -> Option<usize> {
+        self.named.get(&Self::alias(name)).copied()
+    }
+
+    pub fn set(&mut self, name: char, idx: usize) {
+        self.named.insert(Self::alias(name), idx);
+    }
+
+    pub fn remove(&mut self, name: char) {
+        self.named.remove(&Self::alias(name));
+    }
+
+    /// `'` is another name for the context mark.
+    fn alias(name: char) -> char {
+        if name == '\'' {
+            CONTEXT
+        } else {
+            name
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (char, usize)> + '_ {
+        self.named.iter().map(|(name, idx)| (*name, *idx))
+    }
+
+    pub fn jumps(&self) -> &[(u64, usize)] {
+        &self.jumps
+    }
+
+    /// Adds `idx` to the jumplist, `same_line` tells which older entries it replaces.
+    pub fn add_jump(&mut self, seq: u64, idx: usize, same_line: impl Fn(usize) -> bool) {
+        self.jumps.retain(|(_, old)| !same_line(*old));
+        self.jumps.push((seq, idx));
+        let extra = self.jumps.len().saturating_sub(MAX_ENTRIES);
+        self.jumps.drain(..extra);
+    }
+
+    pub fn changes(&self) -> &[usize] {
+        &self.changes
+    }
+
+    /// Records a change of the chars from `start` to `end`, a change on the same line as the
+    /// last one replaces it.
+    pub fn add_change(&mut self, start: usize, end: usize, same_line: impl Fn(usize) -> bool) {
+        self.set(CHANGE_START, start);
+        self.set(CHANGE_END, end);
+        self.set(LAST_CHANGE, start);
+        if self.changes.last().is_some_and(|last| same_line(*last)) {
+            self.changes.pop();
+        }
+        self.changes.push(start);
+        let extra = self.changes.len().saturating_sub(MAX_ENTRIES);
+        self.changes.drain(..extra);
+        self.change_idx = None;
+    }
+
+    /// `len` chars were inserted at `idx`.
+    pub fn inserted(&mut self, idx: usize, len: usize) {
+        let shift = |pos: &mut usize| {
+            if *pos >= idx {
+                *pos += len;
+            }
+        };
+        self.for_each(shift);
+    }
+
+    /// The chars in `range` were removed, marks inside it move to its start.
+    pub fn removed(&mut self, range: Range<usize>) {
+        let shift = |pos: &mut usize| {
+            if *pos >= range.end {
+                *pos -= range.len();
+            } else if *pos > range.start {
+                *pos = range.start;
+            }
+        };
+        self.for_each(shift);
+    }
+
+    fn for_each(&mut self, f: impl Fn(&mut usize)) {
+        self.named.values_mut().for_each(&f);
+        self.jumps.iter_mut().for_each(|(_, idx)| f(idx));
+        self.changes.iter_mut().for_each(&f);
+    }
+}
+
+#[test]
+fn marks_follow_edits() {
+    let mut marks = Marks::default();
+    marks.set('a', 2);
+    marks.set('b', 10);
+    marks.set('\'', 6);
+    marks.add_jump(1, 8, |_| false);
+    marks.inserted(4, 3);
+    assert_eq!(marks.get('a'), Some(2));
+    assert_eq!(marks.get('b'), Some(13));
+    assert_eq!(marks.get(CONTEXT), Some(9));
+    marks.removed(1..10);
+    assert_eq!(marks.get('a'), Some(1));
+    assert_eq!(marks.get('b'), Some(4));
+    assert_eq!(marks.jumps(), &[(1, 2)]);
+}
+
+#[test]
+fn changes_on_one_line_merge() {
+    let mut marks = Marks::default();
+    marks.add_change(0, 0, |_| false);
+    marks.add_change(5, 6, |_| false);
+    marks.add_change(7, 7, |idx| idx == 5);
+    assert_eq!(marks.changes(), &[0, 7]);
+    assert_eq!(marks.get(CHANGE_START), Some(7));
+    assert_eq!(marks.get(LAST_CHANGE), Some(7));
+}
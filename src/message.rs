@@ -1,11 +1,15 @@
 use revi_ui::{
     container::Container,
+    event::MouseEvent,
     layout::{Rect, Size, Stack},
     style::ContentStyle,
     text::Text,
     Color, Keys,
 };
 
+use crate::map_keys::MapCommand;
+use crate::motion::{Motion, Operator};
+use crate::text_object::TextObject;
 use crate::Mode;
 
 #[allow(clippy::enum_variant_names)]
@@ -28,6 +32,8 @@ pub enum Message {
     CheckForMapping,
     ModeCommandInsertStr(String),
     ModeInsertInsertStr(String),
+    /// Text typed in replace mode, over the chars under the cursor.
+    ModeReplaceStr(String),
     ChangeMode(Mode),
     ExecuteCommand,
     BufferList,
@@ -36,11 +42,132 @@ pub enum Message {
     UserMessage(UserMessageBuilder),
     CloseCurrentPaneOnKeyPress,
     NextAvailableCommand,
+    PrevAvailableCommand,
+    DeleteWordBefore,
+    DeleteToLineStart,
+    HistoryUp,
+    HistoryDown,
+    /// The next key names the register to insert on the command line.
+    InsertRegister,
     Resize(Size),
     Save(Option<String>),
+    PromptKey(Keys),
+    RecoverSwap(String),
+    DeleteSwap(String),
+    WriteSwapFiles,
+    MappingTimeout(usize),
+    SetOption(String),
+    /// Keys from a mapping, `true` lets them trigger user mappings.
+    FeedKeys(Vec<Keys>, bool),
+    Map(MapCommand, String),
+    /// Text pasted into the terminal, inserted in one go without going through mappings.
+    Paste(String),
+    Mouse(MouseEvent),
+    /// Give the terminal back to the shell until revi is continued.
+    Suspend,
+    /// Look for files changed on disk by other programs.
+    CheckTime,
+    ReloadBuffer(String),
+    /// The watcher saw these files change.
+    FilesChanged(Vec<String>),
+    /// `q{reg}` starts recording keys into a register.
+    RecordMacro(char),
+    /// `@{reg}` types the keys in a register, `@` for the last one played.
+    PlayMacro(char),
+    /// `.` makes the last change again.
+    RepeatChange,
+    /// `gg` and `G`.
+    CursorTopOfBuffer,
+    CursorBottomOfBuffer,
+    SetMark(char),
+    /// `true` goes to the marked char, `false` to the first non-blank char of its line.
+    GoToMark(char, bool),
+    /// `<C-o>` and `<C-i>` through the jumplist.
+    JumpOlder,
+    JumpNewer,
+    /// `g;` and `g,` through the change list.
+    ChangeOlder,
+    ChangeNewer,
+    ListMarks,
+    ListJumps,
+    /// `d`, `c`, `y`, `>` and `<`, the next motion tells them which text to work on.
+    Operator(Operator),
+    /// Moves the cursor, or picks the text for the pending operator.
+    Motion(Motion),
+    /// `f`, `t`, `F` and `T` wait for the char to find, forward or not and before it or not.
+    FindChar(bool, bool),
+    /// `r` and `gr` wait for the char to replace the ones under the cursor with.
+    ReplaceChar,
+    /// Replaces count chars under the cursor with the char.
+    Replace(char),
+    /// `;` and `,`, `true` looks the other way.
+    RepeatFind(bool),
+    /// `true` for the inner variant like `iw`, `false` for the around one like `aw`.
+    TextObject(TextObject, bool),
+    /// The operator on count lines, like `dd`, or `S` and `Y`.
+    LineOperator(Operator),
+    /// `o` and `O`, `true` opens the line below.
+    OpenLine(bool),
+    /// `J`
+    JoinLines,
+    /// `p` and `P`, `true` puts the unnamed register after the cursor.
+    Put(bool),
+    /// Write a session to the file, `true` replaces an existing one.
+    /// The text is what the `session_save` hook adds to it.
+    MakeSession(String, bool, Option<String>),
     Quit,
 }
 
+impl Message {
+    /// Keys on their way to a mapping and events from outside the editor.
+    /// `.` leaves them out of the change it repeats.
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Self::KeyPress(_)
+                | Self::CheckForMapping
+                | Self::MappingTimeout(_)
+                | Self::FeedKeys(..)
+                | Self::FindChar(..)
+                | Self::ReplaceChar
+                | Self::PlayMacro(_)
+                | Self::RepeatChange
+                | Self::PromptKey(_)
+                | Self::UserMessage(_)
+                | Self::CloseCurrentPaneOnKeyPress
+                | Self::Resize(_)
+                | Self::Mouse(_)
+                | Self::WriteSwapFiles
+                | Self::CheckTime
+                | Self::FilesChanged(_)
+                | Self::ReloadBuffer(_)
+                | Self::Suspend
+        )
+    }
+
+    /// Normal mode messages that change the buffer, `.` repeats them.
+    /// Operators take their motion along and insert mode takes everything up to leaving it.
+    pub fn starts_change(&self) -> bool {
+        matches!(
+            self,
+            Self::Delete
+                | Self::Paste(_)
+                | Self::InsertAtEnd
+                | Self::ChangeMode(Mode::Insert | Mode::Replace)
+                | Self::Replace(_)
+                | Self::Put(_)
+                | Self::OpenLine(_)
+                | Self::JoinLines
+                | Self::LineOperator(
+                    Operator::Delete | Operator::Change | Operator::Indent | Operator::Dedent
+                )
+                | Self::Operator(
+                    Operator::Delete | Operator::Change | Operator::Indent | Operator::Dedent
+                )
+        )
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct UserMessageBuilder {
     message: String,
@@ -80,3 +207,37 @@ impl UserMessageBuilder {
         Container::new(rect, stack).push(msg).push(ft)
     }
 }
+
+/// A user message that waits for one of a set of keys to be pressed.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    builder: UserMessageBuilder,
+    choices: Vec<(char, Message)>,
+}
+
+impl Prompt {
+    pub fn new(builder: UserMessageBuilder) -> Self {
+        Self {
+            builder,
+            choices: Vec::new(),
+        }
+    }
+
+    pub fn choice(mut self, key: char, message: Message) -> Self {
+        self.choices.push((key.to_ascii_lowercase(), message));
+        self
+    }
+
+    /// Message for the pressed key, any key without a choice dismisses the prompt.
+    pub fn answer(&self, key: char) -> Option<Message> {
+        let key = key.to_ascii_lowercase();
+        self.choices
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, message)| message.clone())
+    }
+
+    pub fn as_view(&self, rect: Rect) -> Container {
+        self.builder.as_view(rect)
+    }
+}
@@ -0,0 +1,688 @@
+//! Motions move the cursor over the text in the rope, and tell operators like `d` and `c`
+//! which text to work on.
+use ropey::Rope;
+use std::ops::Range;
+
+/// Chars that make up a word for `w`, `b` and `e`, parsed from the `iskeyword` option.
+/// The option is a comma separated list of `@` for letters, char codes like `48` or
+/// ranges like `192-255`, and single chars like `_`.
+#[derive(Debug, Clone, Default)]
+pub struct Keyword {
+    letters: bool,
+    ranges: Vec<(u32, u32)>,
+}
+
+impl Keyword {
+    pub fn new(spec: &str) -> Self {
+        let mut keyword = Self::default();
+        for part in spec.split(',').filter(|p| !p.is_empty()) {
+            if part == "@" {
+                keyword.letters = true;
+                continue;
+            }
+            let bound = |s: &str| -> Option<u32> {
+                match s.parse() {
+                    Ok(code) => Some(code),
+                    Err(_) => {
+                        let mut chars = s.chars();
+                        let c = chars.next()?;
+                        chars.next().is_none().then_some(c as u32)
+                    }
+                }
+            };
+            // `@-@` is the `@` char itself, `-` alone is a single char too
+            let range = match part
+                .split_once('-')
+                .filter(|(a, b)| !a.is_empty() && !b.is_empty())
+            {
+                Some((from, to)) => bound(from).zip(bound(to)),
+                None => bound(part).map(|c| (c, c)),
+            };
+            keyword.ranges.extend(range);
+        }
+        keyword
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        (self.letters && c.is_alphabetic())
+            || self
+                .ranges
+                .iter()
+                .any(|(from, to)| (*from..=*to).contains(&(c as u32)))
+    }
+}
+
+/// How an operator treats the text between the cursor and the end of a motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The char the motion ends on is left out.
+    Exclusive,
+    /// The char the motion ends on is included.
+    Inclusive,
+    /// Whole lines are used.
+    Linewise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    /// `w` and `W`, `true` for WORDs that only end at blanks.
+    WordForward(bool),
+    /// `b` and `B`.
+    WordBackward(bool),
+    /// `e` and `E`.
+    WordEnd(bool),
+    /// `ge` and `gE`.
+    WordEndBackward(bool),
+    /// `}` and `{`.
+    ParagraphForward,
+    ParagraphBackward,
+    /// `)` and `(`.
+    SentenceForward,
+    SentenceBackward,
+    /// `f`, `t`, `F` and `T` with the char to look for on the cursor line.
+    Find(Find),
+    /// `%` goes to the bracket matching the one under or after the cursor.
+    /// Brackets in strings and comments count too until there is highlighting to tell them apart.
+    MatchBracket,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Find {
+    pub c: char,
+    pub forward: bool,
+    /// `t` and `T` stop next to the char.
+    pub till: bool,
+    /// `;` and `,` skip a match right next to the cursor, so `t` does not get stuck on it.
+    pub again: bool,
+}
+
+impl Find {
+    /// `,` looks the other way.
+    pub fn reversed(self) -> Self {
+        Self {
+            forward: !self.forward,
+            ..self
+        }
+    }
+}
+
+/// Commands that work on the text a motion moves over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `d`
+    Delete,
+    /// `c`, deletes and enters insert mode.
+    Change,
+    /// `y`
+    Yank,
+    /// `>`, shifts the lines right by `shiftwidth`.
+    Indent,
+    /// `<`, shifts the lines left by `shiftwidth`.
+    Dedent,
+}
+
+/// Text an operator works on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub range: Range<usize>,
+    pub linewise: bool,
+}
+
+impl Motion {
+    pub fn kind(self) -> Kind {
+        match self {
+            Self::Up | Self::Down | Self::FirstLine | Self::LastLine => Kind::Linewise,
+            Self::LineEnd | Self::WordEnd(_) | Self::WordEndBackward(_) | Self::MatchBracket => {
+                Kind::Inclusive
+            }
+            Self::Find(find) if find.forward => Kind::Inclusive,
+            _ => Kind::Exclusive,
+        }
+    }
+
+    /// Moving through paragraphs and sentences and to a matching bracket adds to the jumplist.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Self::ParagraphForward
+                | Self::ParagraphBackward
+                | Self::SentenceForward
+                | Self::SentenceBackward
+                | Self::MatchBracket
+        )
+    }
+
+    /// Where `count` of the motion take the cursor from char index `idx`.
+    /// `None` when there is nowhere to go, like `f` without the char on the line.
+    pub fn apply(self, rope: &Rope, idx: usize, count: usize, keyword: &Keyword) -> Option<usize> {
+        let text = Text { rope, keyword };
+        let idx = idx.min(rope.len_chars());
+        match self {
+            Self::LineEnd => Some(text.line_end(text.line(idx).saturating_add(count - 1))),
+            Self::FirstLine => Some(text.line_start(count.saturating_sub(1))),
+            Self::LastLine => Some(text.line_start(text.last_line())),
+            Self::Find(find) => text.find(idx, find, count),
+            Self::MatchBracket => text.match_bracket(idx),
+            _ => Some((0..count).fold(idx, |idx, _| self.step(&text, idx))),
+        }
+    }
+
+    fn step(self, text: &Text, idx: usize) -> usize {
+        match self {
+            Self::Left => match text.col(idx) {
+                0 => idx,
+                _ => idx - 1,
+            },
+            // stops on the newline so operators can take the last char of a line
+            Self::Right => match text.char(idx) {
+                Some('\n') | None => idx,
+                _ => idx + 1,
+            },
+            Self::Up | Self::Down => {
+                let line = text.line(idx);
+                let line = match self {
+                    Self::Up => line.saturating_sub(1),
+                    _ => (line + 1).min(text.last_line()),
+                };
+                let col = text.col(idx).min(text.line_len(line).saturating_sub(1));
+                text.line_start(line) + col
+            }
+            Self::LineStart => text.line_start(text.line(idx)),
+            Self::WordForward(big) => text.word_forward(idx, big),
+            Self::WordBackward(big) => text.word_backward(idx, big),
+            Self::WordEnd(big) => text.word_end(idx, big),
+            Self::WordEndBackward(big) => text.word_end_backward(idx, big),
+            Self::ParagraphForward => text.paragraph_forward(idx),
+            Self::ParagraphBackward => text.paragraph_backward(idx),
+            Self::SentenceForward => text.sentence_forward(idx),
+            Self::SentenceBackward => text.sentence_backward(idx),
+            Self::LineEnd
+            | Self::FirstLine
+            | Self::LastLine
+            | Self::Find(_)
+            | Self::MatchBracket => idx,
+        }
+    }
+
+    /// The text an operator works on when the motion is used from char index `idx`.
+    /// `change` is set for `c`, which treats `cw` like `ce` the way vim does.
+    pub fn target(
+        self,
+        rope: &Rope,
+        idx: usize,
+        count: usize,
+        keyword: &Keyword,
+        change: bool,
+    ) -> Option<Target> {
+        let text = Text { rope, keyword };
+        let idx = idx.min(rope.len_chars());
+        let (motion, mut end) = match self {
+            Self::WordForward(big)
+                if change && text.char(idx).is_some_and(|c| !c.is_whitespace()) =>
+            {
+                // `cw` on the last char of a word starts counting words from it
+                let count = match text.class(idx, big) != text.class(idx + 1, big) {
+                    true => count - 1,
+                    false => count,
+                };
+                (
+                    Self::WordEnd(big),
+                    Self::WordEnd(big).apply(rope, idx, count, keyword)?,
+                )
+            }
+            Self::WordForward(_) => {
+                let before = self.apply(rope, idx, count - 1, keyword)?;
+                let end = self.step(&text, before);
+                // the last word moved over ends the text, not the start of the next line
+                let end = if text.line(end) > text.line(before) {
+                    text.line_end(text.line(before)) + 1
+                } else {
+                    end
+                };
+                (self, end.max(idx))
+            }
+            _ => (self, self.apply(rope, idx, count, keyword)?),
+        };
+        let (mut start, kind) = (idx.min(end), motion.kind());
+        end = idx.max(end);
+        if end >= rope.len_chars() && kind == Kind::Exclusive {
+            end = rope.len_chars();
+        }
+        let target = match kind {
+            Kind::Linewise => text.lines(text.line(start), text.line(end)),
+            Kind::Inclusive => Target {
+                range: start..(end + 1).min(rope.len_chars()),
+                linewise: false,
+            },
+            Kind::Exclusive
+                if end > start && text.line(end) > text.line(start) && text.col(end) == 0 =>
+            {
+                // an exclusive motion that ends at the start of a line stops at the end of the
+                // line before, and takes whole lines when it started before the first non-blank
+                let (first, last) = (text.line(start), text.line(end) - 1);
+                if start <= text.first_non_blank(first) {
+                    return Some(text.lines(first, last));
+                }
+                start = start.min(end - 1);
+                Target {
+                    range: start..end - 1,
+                    linewise: false,
+                }
+            }
+            Kind::Exclusive => Target {
+                range: start..end,
+                linewise: false,
+            },
+        };
+        Some(target)
+    }
+}
+
+/// The rope with the word chars of the buffer, motions and text objects find their way in it.
+pub struct Text<'a> {
+    rope: &'a Rope,
+    keyword: &'a Keyword,
+}
+
+impl<'a> Text<'a> {
+    pub fn new(rope: &'a Rope, keyword: &'a Keyword) -> Self {
+        Self { rope, keyword }
+    }
+
+    pub fn char(&self, idx: usize) -> Option<char> {
+        self.rope.get_char(idx)
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn line(&self, idx: usize) -> usize {
+        self.rope.char_to_line(idx.min(self.rope.len_chars()))
+    }
+
+    /// Index of the last line, the empty line after a trailing newline does not count.
+    pub fn last_line(&self) -> usize {
+        let lines = self.rope.len_lines();
+        if lines > 1 && self.rope.line(lines - 1).len_chars() == 0 {
+            return lines - 2;
+        }
+        lines.saturating_sub(1)
+    }
+
+    pub fn col(&self, idx: usize) -> usize {
+        idx - self.rope.line_to_char(self.line(idx))
+    }
+
+    pub fn line_start(&self, line: usize) -> usize {
+        self.rope.line_to_char(line.min(self.last_line()))
+    }
+
+    /// Chars in `line` without its newline.
+    pub fn line_len(&self, line: usize) -> usize {
+        let line = self.rope.line(line.min(self.last_line()));
+        line.len_chars() - usize::from(line.chars().last() == Some('\n'))
+    }
+
+    /// The last char of `line`, its start when it is empty.
+    pub fn line_end(&self, line: usize) -> usize {
+        let line = line.min(self.last_line());
+        self.line_start(line) + self.line_len(line).saturating_sub(1)
+    }
+
+    pub fn first_non_blank(&self, line: usize) -> usize {
+        let start = self.line_start(line);
+        let blanks = self
+            .rope
+            .chars_at(start)
+            .take(self.line_len(line))
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .count();
+        start + blanks
+    }
+
+    pub fn is_empty_line(&self, line: usize) -> bool {
+        self.line_len(line) == 0
+    }
+
+    /// Whole lines from `first` to `last` with their newlines.
+    pub fn lines(&self, first: usize, last: usize) -> Target {
+        let start = self.line_start(first);
+        let end = if last + 1 < self.rope.len_lines() {
+            self.rope.line_to_char(last + 1)
+        } else {
+            self.rope.len_chars()
+        };
+        Target {
+            range: start..end,
+            linewise: true,
+        }
+    }
+
+    /// 0 for blanks, 1 for punctuation and 2 for word chars. WORDs only tell blanks apart.
+    pub fn class(&self, idx: usize, big: bool) -> u8 {
+        match self.char(idx) {
+            None => 0,
+            Some(c) if c.is_whitespace() => 0,
+            Some(_) if big => 1,
+            Some(c) if self.keyword.contains(c) => 2,
+            Some(_) => 1,
+        }
+    }
+
+    /// An empty line counts as a word.
+    fn is_empty_line_at(&self, idx: usize) -> bool {
+        self.char(idx) == Some('\n') && (idx == 0 || self.char(idx - 1) == Some('\n'))
+    }
+
+    fn word_forward(&self, mut idx: usize, big: bool) -> usize {
+        let len = self.rope.len_chars();
+        let class = self.class(idx, big);
+        if class != 0 {
+            while idx < len && self.class(idx, big) == class {
+                idx += 1;
+            }
+        } else if self.is_empty_line_at(idx) {
+            idx += 1;
+        }
+        while idx < len && self.class(idx, big) == 0 && !self.is_empty_line_at(idx) {
+            idx += 1;
+        }
+        idx.min(len.saturating_sub(1))
+    }
+
+    fn word_backward(&self, mut idx: usize, big: bool) -> usize {
+        if idx == 0 {
+            return 0;
+        }
+        idx -= 1;
+        while idx > 0 && self.class(idx, big) == 0 && !self.is_empty_line_at(idx) {
+            idx -= 1;
+        }
+        let class = self.class(idx, big);
+        if class != 0 {
+            while idx > 0 && self.class(idx - 1, big) == class {
+                idx -= 1;
+            }
+        }
+        idx
+    }
+
+    fn word_end(&self, mut idx: usize, big: bool) -> usize {
+        let len = self.rope.len_chars();
+        idx += 1;
+        while idx < len && self.class(idx, big) == 0 {
+            idx += 1;
+        }
+        let class = self.class(idx, big);
+        while idx + 1 < len && self.class(idx + 1, big) == class {
+            idx += 1;
+        }
+        idx.min(len.saturating_sub(1))
+    }
+
+    fn word_end_backward(&self, mut idx: usize, big: bool) -> usize {
+        let class = self.class(idx, big);
+        if class != 0 {
+            while idx > 0 && self.class(idx, big) == class {
+                idx -= 1;
+            }
+        } else {
+            idx = idx.saturating_sub(1);
+        }
+        while idx > 0 && self.class(idx, big) == 0 && !self.is_empty_line_at(idx) {
+            idx -= 1;
+        }
+        idx
+    }
+
+    /// The next empty line after the paragraph, or the end of the last line.
+    fn paragraph_forward(&self, idx: usize) -> usize {
+        let last = self.last_line();
+        let mut line = self.line(idx);
+        while line < last && self.is_empty_line(line) {
+            line += 1;
+        }
+        while line < last && !self.is_empty_line(line) {
+            line += 1;
+        }
+        match self.is_empty_line(line) {
+            true => self.line_start(line),
+            false => self.line_end(line),
+        }
+    }
+
+    fn paragraph_backward(&self, idx: usize) -> usize {
+        let mut line = self.line(idx);
+        while line > 0 && self.is_empty_line(line) {
+            line -= 1;
+        }
+        while line > 0 && !self.is_empty_line(line) {
+            line -= 1;
+        }
+        self.line_start(line)
+    }
+
+    /// A sentence starts after `.`, `!` or `?` and a blank, closing brackets and quotes may
+    /// come between. Empty lines and the first non-blank after them start sentences too.
+    pub fn is_sentence_start(&self, idx: usize) -> bool {
+        if self.is_empty_line_at(idx) {
+            return true;
+        }
+        if self.class(idx, true) == 0 {
+            return false;
+        }
+        let mut before = idx;
+        let mut blanks = 0;
+        while before > 0 && self.class(before - 1, true) == 0 {
+            before -= 1;
+            if self.is_empty_line_at(before) {
+                return true;
+            }
+            blanks += 1;
+        }
+        if before == 0 {
+            return true;
+        }
+        if blanks == 0 {
+            return false;
+        }
+        let mut end = before - 1;
+        while end > 0 && matches!(self.char(end), Some(')' | ']' | '"' | '\'')) {
+            end -= 1;
+        }
+        matches!(self.char(end), Some('.' | '!' | '?'))
+    }
+
+    fn sentence_forward(&self, idx: usize) -> usize {
+        let len = self.rope.len_chars();
+        (idx + 1..len)
+            .find(|idx| self.is_sentence_start(*idx))
+            .unwrap_or(len.saturating_sub(1))
+    }
+
+    /// The `count`th `find.c` on the line of `idx`.
+    fn find(&self, idx: usize, find: Find, count: usize) -> Option<usize> {
+        let line = self.line(idx);
+        let (start, end) = (
+            self.line_start(line),
+            self.line_start(line) + self.line_len(line),
+        );
+        let skip = usize::from(find.till && find.again);
+        let found = if find.forward {
+            (idx + 1 + skip..end)
+                .filter(|i| self.char(*i) == Some(find.c))
+                .nth(count - 1)?
+        } else {
+            (start..idx.saturating_sub(skip))
+                .rev()
+                .filter(|i| self.char(*i) == Some(find.c))
+                .nth(count - 1)?
+        };
+        match (find.till, find.forward) {
+            (false, _) => Some(found),
+            (true, true) => Some(found - 1),
+            (true, false) => Some(found + 1),
+        }
+    }
+
+    /// The bracket matching the first one at or after `idx` on its line.
+    fn match_bracket(&self, idx: usize) -> Option<usize> {
+        const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+        let line = self.line(idx);
+        let end = self.line_start(line) + self.line_len(line);
+        let (at, c) = (idx..end).find_map(|i| {
+            let c = self.char(i)?;
+            PAIRS
+                .iter()
+                .any(|(open, close)| c == *open || c == *close)
+                .then_some((i, c))
+        })?;
+        let (open, close) = *PAIRS.iter().find(|(o, cl)| c == *o || c == *cl)?;
+        let mut depth = 0usize;
+        let mut check = |i: usize| {
+            match self.char(i) {
+                Some(ch) if ch == c => depth += 1,
+                Some(ch) if ch == open || ch == close => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        };
+        if c == open {
+            (at..self.rope.len_chars()).find(|i| check(*i))
+        } else {
+            (0..=at).rev().find(|i| check(*i))
+        }
+    }
+
+    fn sentence_backward(&self, idx: usize) -> usize {
+        (0..idx)
+            .rev()
+            .find(|idx| self.is_sentence_start(*idx))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+fn walk(motion: Motion, src: &str, from: usize, steps: usize) -> Vec<usize> {
+    let rope = Rope::from_str(src);
+    let keyword = Keyword::new("@,48-57,_,192-255");
+    (0..steps)
+        .scan(from, |idx, _| {
+            *idx = motion.apply(&rope, *idx, 1, &keyword)?;
+            Some(*idx)
+        })
+        .collect()
+}
+
+#[test]
+fn word_motions() {
+    let src = "foo.bar baz\n\n  qux";
+    assert_eq!(
+        walk(Motion::WordForward(false), src, 0, 5),
+        vec![3, 4, 8, 12, 15]
+    );
+    assert_eq!(walk(Motion::WordForward(true), src, 0, 3), vec![8, 12, 15]);
+    assert_eq!(
+        walk(Motion::WordBackward(false), src, 15, 4),
+        vec![12, 8, 4, 3]
+    );
+    assert_eq!(walk(Motion::WordEnd(false), src, 0, 4), vec![2, 3, 6, 10]);
+    assert_eq!(walk(Motion::WordEnd(true), src, 0, 2), vec![6, 10]);
+    assert_eq!(
+        walk(Motion::WordEndBackward(false), src, 16, 3),
+        vec![12, 10, 6]
+    );
+}
+
+#[test]
+fn iskeyword_changes_words() {
+    let keyword = Keyword::new("@,-,48-57");
+    assert!(keyword.contains('-') && keyword.contains('7') && keyword.contains('é'));
+    assert!(!keyword.contains('_'));
+    let rope = Rope::from_str("foo-bar baz");
+    assert_eq!(
+        Motion::WordForward(false).apply(&rope, 0, 1, &keyword),
+        Some(8)
+    );
+}
+
+#[test]
+fn paragraph_and_sentence_motions() {
+    let src = "One. Two!  Three\nfour.\n\nFive (six.) Seven\n";
+    assert_eq!(
+        walk(Motion::SentenceForward, src, 0, 5),
+        vec![5, 11, 23, 24, 36]
+    );
+    assert_eq!(walk(Motion::SentenceBackward, src, 36, 3), vec![24, 23, 11]);
+    assert_eq!(walk(Motion::ParagraphForward, src, 0, 2), vec![23, 40]);
+    assert_eq!(walk(Motion::ParagraphBackward, src, 40, 2), vec![23, 0]);
+}
+
+#[test]
+fn operator_targets() {
+    let rope = Rope::from_str("foo bar\nbaz\n");
+    let keyword = Keyword::new("@");
+    let target =
+        |motion: Motion, idx, count, change| motion.target(&rope, idx, count, &keyword, change);
+    let chars = |range: Range<usize>| {
+        Some(Target {
+            range,
+            linewise: false,
+        })
+    };
+    assert_eq!(target(Motion::WordForward(false), 0, 1, false), chars(0..4));
+    assert_eq!(target(Motion::WordForward(false), 0, 1, true), chars(0..3));
+    // `dw` on the last word of a line keeps the newline
+    assert_eq!(target(Motion::WordForward(false), 4, 1, false), chars(4..7));
+    assert_eq!(target(Motion::WordEnd(false), 0, 2, false), chars(0..7));
+    assert_eq!(
+        target(Motion::Down, 1, 1, false),
+        Some(Target {
+            range: 0..12,
+            linewise: true
+        })
+    );
+    assert_eq!(target(Motion::LineEnd, 4, 1, false), chars(4..7));
+}
+
+#[test]
+fn find_and_match_bracket() {
+    let rope = Rope::from_str("a(b[c]d)e x\n{\n  (}\n}\n");
+    let keyword = Keyword::default();
+    let apply = |motion: Motion, idx, count| motion.apply(&rope, idx, count, &keyword);
+    let find = |c, forward, till, again| {
+        Motion::Find(Find {
+            c,
+            forward,
+            till,
+            again,
+        })
+    };
+    assert_eq!(apply(find('d', true, false, false), 0, 1), Some(6));
+    assert_eq!(apply(find(')', true, true, false), 0, 1), Some(6));
+    assert_eq!(apply(find('(', false, false, false), 8, 1), Some(1));
+    assert_eq!(apply(find('b', false, true, false), 8, 1), Some(3));
+    // finds stay on the line
+    assert_eq!(apply(find('{', true, false, false), 0, 1), None);
+    // `;` after `t` moves past the match next to the cursor
+    let dots = Rope::from_str("a.b.c");
+    assert_eq!(
+        find('.', true, true, true).apply(&dots, 0, 1, &keyword),
+        Some(2)
+    );
+    assert_eq!(apply(Motion::MatchBracket, 0, 1), Some(7));
+    assert_eq!(apply(Motion::MatchBracket, 7, 1), Some(1));
+    assert_eq!(apply(Motion::MatchBracket, 4, 1), Some(3));
+    // only brackets of the same kind nest
+    assert_eq!(apply(Motion::MatchBracket, 12, 1), Some(17));
+    assert_eq!(apply(Motion::MatchBracket, 8, 1), None);
+}
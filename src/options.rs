@@ -0,0 +1,211 @@
+/// Value of an option that can be changed with `:set`.
+trait OptionValue {
+    fn set_str(&mut self, value: &str) -> Result<(), String>;
+    fn set_flag(&mut self, on: bool) -> Result<(), String>;
+    fn display(&self) -> String;
+}
+
+impl OptionValue for bool {
+    fn set_str(&mut self, value: &str) -> Result<(), String> {
+        *self = match value {
+            "true" | "on" | "1" => true,
+            "false" | "off" | "0" => false,
+            _ => return Err(format!("invalid value for boolean option: {value}")),
+        };
+        Ok(())
+    }
+
+    fn set_flag(&mut self, on: bool) -> Result<(), String> {
+        *self = on;
+        Ok(())
+    }
+
+    fn display(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl OptionValue for u64 {
+    fn set_str(&mut self, value: &str) -> Result<(), String> {
+        *self = value
+            .parse()
+            .map_err(|_| format!("number required: {value}"))?;
+        Ok(())
+    }
+
+    fn set_flag(&mut self, _: bool) -> Result<(), String> {
+        Err("number required".into())
+    }
+
+    fn display(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl OptionValue for String {
+    fn set_str(&mut self, value: &str) -> Result<(), String> {
+        *self = value.to_string();
+        Ok(())
+    }
+
+    fn set_flag(&mut self, _: bool) -> Result<(), String> {
+        Err("string required".into())
+    }
+
+    fn display(&self) -> String {
+        self.clone()
+    }
+}
+
+macro_rules! options {
+    ($($(#[doc = $doc:expr])* $name:ident: $ty:ty = $default:expr),* $(,)?) => {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Options {
+            $($(#[doc = $doc])* pub $name: $ty,)*
+        }
+
+        impl Default for Options {
+            fn default() -> Self {
+                Self {
+                    $($name: $default.into(),)*
+                }
+            }
+        }
+
+        impl Options {
+            /// Names of every option, used for completion.
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($name)),*];
+
+            fn value(&self, name: &str) -> Option<&dyn OptionValue> {
+                match name {
+                    $(stringify!($name) => Some(&self.$name),)*
+                    _ => None,
+                }
+            }
+
+            fn value_mut(&mut self, name: &str) -> Option<&mut dyn OptionValue> {
+                match name {
+                    $(stringify!($name) => Some(&mut self.$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+options! {
+    /// Wait `timeoutlen` for the rest of an ambiguous mapping, wait forever when off.
+    timeout: bool = true,
+    /// Milliseconds to wait for the next key of a mapping.
+    timeoutlen: u64 = 1000u64,
+    /// Milliseconds to wait for the next key when a mapping starts with a special key like `<esc>`.
+    ttimeoutlen: u64 = 50u64,
+    /// Watch the files of open buffers and reload them when other programs change them.
+    watchfiles: bool = true,
+    /// Reload files changed outside of revi when the buffer has no changes of its own.
+    autoread: bool = false,
+    /// Capture the mouse to click, drag and scroll in the editor.
+    mouse: bool = false,
+    /// Number of command lines to remember.
+    history: u64 = 100u64,
+    /// Show command line completions in a popup.
+    wildmenu: bool = true,
+    /// Complete command line words that contain the typed characters in order, not just the ones starting with them.
+    wildfuzzy: bool = false,
+    /// Show the keys that can follow pending keys in a popup.
+    whichkey: bool = false,
+    /// Keys `<leader>` stands for in mappings.
+    mapleader: String = "\\",
+    /// Keys `<localleader>` stands for in mappings.
+    maplocalleader: String = "\\",
+    /// Chars that make up a word for `w`, `b` and `e`: `@` for letters, char codes and
+    /// ranges of them like `48-57`, and single chars.
+    iskeyword: String = "@,48-57,_,192-255",
+    /// Columns `>>` and `<<` shift lines by.
+    shiftwidth: u64 = 4u64,
+    /// New lines from `o` and `O` start with the indent of the line they are opened from,
+    /// and `cc` keeps the indent of the line.
+    autoindent: bool = true,
+}
+
+impl Options {
+    /// Handles the argument of `:set`.
+    /// `name=value`, `name` and `noname` change an option, `name?` shows it.
+    /// Returns the text to show to the user, if any.
+    pub fn set(&mut self, arg: &str) -> Result<Option<String>, String> {
+        let arg = arg.trim();
+        if arg.is_empty() {
+            return Ok(Some(self.to_string()));
+        }
+        if let Some(name) = arg.strip_suffix('?') {
+            let value = self.get(name)?;
+            return Ok(Some(format!("{name}={value}")));
+        }
+        if let Some((name, value)) = arg.split_once('=') {
+            self.lookup_mut(name)?.set_str(value)?;
+            return Ok(None);
+        }
+        if let Some(name) = arg.strip_prefix("no") {
+            if let Ok(value) = self.lookup_mut(name) {
+                value.set_flag(false)?;
+                return Ok(None);
+            }
+        }
+        let value = self.lookup_mut(arg)?;
+        match value.set_flag(true) {
+            Ok(()) => Ok(None),
+            // `:set tabstop` shows numbers and strings like vim does
+            Err(_) => Ok(Some(format!("{arg}={}", value.display()))),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<String, String> {
+        self.value(name)
+            .map(OptionValue::display)
+            .ok_or_else(|| format!("unknown option: {name}"))
+    }
+
+    /// `name=value` of every option that differs from its default, in a form `set` accepts.
+    pub fn changed(&self) -> Vec<String> {
+        let default = Self::default();
+        Self::NAMES
+            .iter()
+            .filter_map(|name| {
+                let value = self.get(name).ok()?;
+                (default.get(name).ok()? != value).then(|| format!("{name}={value}"))
+            })
+            .collect()
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Result<&mut dyn OptionValue, String> {
+        self.value_mut(name)
+            .ok_or_else(|| format!("unknown option: {name}"))
+    }
+}
+
+impl std::fmt::Display for Options {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = Self::NAMES
+            .iter()
+            .filter_map(|name| self.value(name).map(|v| format!("{name}={}", v.display())))
+            .collect::<Vec<_>>();
+        write!(f, "{}", list.join("  "))
+    }
+}
+
+#[test]
+fn set_options() {
+    let mut options = Options::default();
+    assert_eq!(options.set("timeoutlen=500"), Ok(None));
+    assert_eq!(options.timeoutlen, 500);
+    assert_eq!(options.set("notimeout"), Ok(None));
+    assert!(!options.timeout);
+    assert_eq!(options.set("timeout"), Ok(None));
+    assert!(options.timeout);
+    assert_eq!(
+        options.set("ttimeoutlen?"),
+        Ok(Some("ttimeoutlen=50".to_string()))
+    );
+    assert!(options.set("timeoutlen=abc").is_err());
+    assert!(options.set("nosuchoption").is_err());
+}
@@ -0,0 +1,24 @@
+use std::path::{Path, PathBuf};
+
+/// Directory revi keeps its runtime state in.
+/// `$XDG_STATE_HOME/revi`, falling back to `~/.local/state/revi` and then the temp dir.
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("revi");
+    }
+    if let Some(home) = std::env::var_os("HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(home).join(".local/state/revi");
+    }
+    std::env::temp_dir().join("revi")
+}
+
+/// `name` as an absolute path, symlinks are resolved when the file exists.
+pub fn absolute(name: &str) -> PathBuf {
+    let path = Path::new(name);
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
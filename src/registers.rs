@@ -0,0 +1,66 @@
+//! Registers hold text to insert later, like vim's `"a` to `"z`.
+use crate::state_file::now;
+use std::collections::HashMap;
+
+/// Register used when none is named, `x` puts deleted text here.
+pub const UNNAMED: char = '"';
+/// The last command line that was executed.
+pub const LAST_COMMAND: char = ':';
+
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    /// Text of each register and when it was set, in seconds since the epoch.
+    text: HashMap<char, (String, u64)>,
+}
+
+impl Registers {
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphabetic() || matches!(name, UNNAMED | LAST_COMMAND)
+    }
+
+    pub fn get(&self, name: char) -> Option<&str> {
+        self.text
+            .get(&name.to_ascii_lowercase())
+            .map(|(text, _)| text.as_str())
+    }
+
+    /// Every register that holds text with the time it was set.
+    pub fn iter(&self) -> impl Iterator<Item = (char, &str, u64)> {
+        self.text
+            .iter()
+            .map(|(name, (text, time))| (*name, text.as_str(), *time))
+    }
+
+    /// Puts back a register saved in an earlier session.
+    pub fn restore(&mut self, name: char, text: &str, time: u64) {
+        if Self::is_valid(name) {
+            self.text
+                .insert(name.to_ascii_lowercase(), (text.to_string(), time));
+        }
+    }
+
+    /// Uppercase names append to the lowercase register. Returns `false` for names that are not registers.
+    pub fn set(&mut self, name: char, text: &str) -> bool {
+        if !Self::is_valid(name) {
+            return false;
+        }
+        let (register, time) = self.text.entry(name.to_ascii_lowercase()).or_default();
+        if !name.is_ascii_uppercase() {
+            register.clear();
+        }
+        register.push_str(text);
+        *time = now();
+        true
+    }
+}
+
+#[test]
+fn uppercase_appends() {
+    let mut registers = Registers::default();
+    assert!(registers.set('a', "foo"));
+    assert!(registers.set('A', "bar"));
+    assert_eq!(registers.get('a'), Some("foobar"));
+    assert_eq!(registers.get('A'), Some("foobar"));
+    assert!(!registers.set('!', "x"));
+    assert_eq!(registers.get('b'), None);
+}
@@ -0,0 +1,149 @@
+//! Sessions written by `:mksession` and opened with `revi -S`.
+//! A session keeps the open files with their cursor and scroll position, which one has focus
+//! and the options changed from their defaults. revi shows one window, so the buffer with focus
+//! is the whole window layout; splits will need a layout entry of their own. The `session_save`
+//! hook in `init.rhai` can add its own text, which is handed to `session_load` when the session
+//! is opened again.
+use crate::state_file::{escape, unescape};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+const MAGIC: &str = "revi-session 1";
+/// Written by `:mksession` without a file name.
+pub const DEFAULT_NAME: &str = "Session.revi";
+
+/// An open file and where its view was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionBuffer {
+    /// Absolute path of the file.
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+    /// First line and column shown in the text area.
+    pub scroll_line: usize,
+    pub scroll_col: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    pub buffers: Vec<SessionBuffer>,
+    /// Index into `buffers` of the buffer with focus.
+    pub focused: usize,
+    /// `name=value` of every option that differs from its default.
+    pub options: Vec<String>,
+    /// Text from the `session_save` hook.
+    pub data: Option<String>,
+}
+
+impl Session {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let src = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&src).ok_or_else(|| format!("{}: not a revi session", path.display()))
+    }
+
+    /// `None` when `src` is not a session, lines that do not parse are skipped.
+    fn parse(src: &str) -> Option<Self> {
+        let mut lines = src.lines();
+        if lines.next() != Some(MAGIC) {
+            return None;
+        }
+        let mut session = Self::default();
+        for line in lines {
+            let Some((kind, rest)) = line.split_once(' ') else {
+                continue;
+            };
+            let _ = match kind {
+                "buffer" => session.parse_buffer(rest),
+                "focus" => rest.parse().ok().map(|idx| session.focused = idx),
+                "option" => {
+                    session.options.push(rest.to_string());
+                    Some(())
+                }
+                "data" => {
+                    session.data = Some(unescape(rest));
+                    Some(())
+                }
+                _ => None,
+            };
+        }
+        Some(session)
+    }
+
+    fn parse_buffer(&mut self, rest: &str) -> Option<()> {
+        let mut parts = rest.splitn(5, ' ');
+        let line = parts.next()?.parse().ok()?;
+        let col = parts.next()?.parse().ok()?;
+        let scroll_line = parts.next()?.parse().ok()?;
+        let scroll_col = parts.next()?.parse().ok()?;
+        let path = parts.next()?.to_string();
+        self.buffers.push(SessionBuffer {
+            path,
+            line,
+            col,
+            scroll_line,
+            scroll_col,
+        });
+        Some(())
+    }
+
+    /// Writes the session to `path`, an existing file is only replaced with `force`.
+    pub fn write(&self, path: &Path, force: bool) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .create_new(!force)
+            .truncate(true)
+            .open(path)?;
+        write!(file, "{self}")?;
+        file.flush()
+    }
+}
+
+impl std::fmt::Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{MAGIC}")?;
+        for option in &self.options {
+            writeln!(f, "option {option}")?;
+        }
+        for b in &self.buffers {
+            writeln!(
+                f,
+                "buffer {} {} {} {} {}",
+                b.line, b.col, b.scroll_line, b.scroll_col, b.path
+            )?;
+        }
+        writeln!(f, "focus {}", self.focused)?;
+        if let Some(data) = &self.data {
+            writeln!(f, "data {}", escape(data))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn session_round_trip() {
+    let session = Session {
+        buffers: vec![
+            SessionBuffer {
+                path: "/tmp/some file.rs".into(),
+                line: 10,
+                col: 2,
+                scroll_line: 8,
+                scroll_col: 0,
+            },
+            SessionBuffer {
+                path: "/tmp/other.rs".into(),
+                line: 0,
+                col: 0,
+                scroll_line: 0,
+                scroll_col: 0,
+            },
+        ],
+        focused: 1,
+        options: vec!["mouse=true".into(), "mapleader= ".into()],
+        data: Some("{\"a\": 1}\nsecond line".into()),
+    };
+    assert_eq!(Session::parse(&session.to_string()), Some(session));
+    assert_eq!(Session::parse("revi-state 1\nfocus 1"), None);
+}
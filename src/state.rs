@@ -1,29 +1,123 @@
 use revi_ui::application::App;
 use revi_ui::container::Container;
-use revi_ui::event::Event;
+use revi_ui::event::{Event, MouseButton, MouseEvent, MouseEventKind};
 use revi_ui::layout::{Alignment, Pos, Rect, Stack};
 use revi_ui::text::Text;
-use revi_ui::{layout::Size, size, Keys};
+use revi_ui::widget::BoxWidget;
+use revi_ui::{keys_to_string, layout::Size, runtime, size, string_to_keys, Key, Keys, Modifiers};
 use revi_ui::{Attribute, Color, SetCursorStyle, Subscription};
 
 use super::{Mode, Settings};
-use crate::buffer::Buffer;
-use crate::map_keys::Mapper;
+use crate::buffer::{Buffer, NO_NAME};
+use crate::completion::Completion;
+use crate::history::History;
+use crate::map_keys::{Binding, MapCommand, Mapper, Mapping};
+use crate::marks::{CONTEXT, LAST_INSERT};
 use crate::message::Message;
-use crate::message::UserMessageBuilder;
+use crate::message::{Prompt, UserMessageBuilder};
+use crate::motion::{Find, Keyword, Motion, Operator, Target};
+use crate::options::Options;
 use crate::parse_keys::KeyParser;
+use crate::registers::{Registers, LAST_COMMAND, UNNAMED};
+use crate::session::{self, Session, SessionBuffer};
+use crate::state_file::{self, FileMark, Position, StateFile};
+use crate::swap::{self, SwapFile};
+use crate::text_object::TextObject;
 use crate::trie;
+use crate::watch;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn absolute_name(name: &str) -> String {
+    crate::paths::absolute(name).to_string_lossy().to_string()
+}
+
+/// Swap files of modified buffers are written this long after the first unswapped edit.
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+/// A buffer with this many unswapped edits is written right away.
+const SWAP_CHANGES: usize = 200;
+/// Mappings may expand into other mappings this many times before we give up.
+const MAX_MAP_DEPTH: usize = 100;
+/// Lines scrolled by one step of the mouse wheel.
+const WHEEL_LINES: usize = 3;
+/// Text repeated by a count before insert mode is dropped when it would grow past this many bytes.
+const MAX_REPEAT_LEN: usize = 1 << 24;
 
 #[derive(Debug)]
 pub struct State {
     pub focused: usize,
     pub buffers: Vec<Buffer>,
     pub messages: Vec<UserMessageBuilder>,
+    pub prompts: Vec<Prompt>,
     pub command_list: trie::Trie,
-    pub tab_index: usize,
+    /// Completion of the command line, until the line is edited some other way.
+    pub completion: Option<Completion>,
+    pub history: History,
+    pub registers: Registers,
+    /// Set after `<C-r>` on the command line, the next key names the register to insert.
+    pub pending_register: bool,
+    /// Register a macro is being recorded into with the keys typed so far.
+    pub recording: Option<(char, Vec<Keys>)>,
+    /// Register `@@` plays again.
+    pub last_macro: Option<char>,
+    /// Count typed before a command in normal and visual mode.
+    pub pending_count: Option<usize>,
+    /// Count of the command that is running, taken with `take_count`.
+    pub count: Option<usize>,
+    /// Char index insert mode was entered at and the count it was entered with,
+    /// `true` when each copy the count makes goes on a line of its own like after `o`.
+    pub insert: Option<(usize, usize, bool)>,
+    /// Set after `f`, `t`, `F` and `T` to whether they look forward and stop before the char,
+    /// the next key is the char to find.
+    pub pending_find: Option<(bool, bool)>,
+    /// Last `f`, `t`, `F` or `T`, `;` and `,` find its char again.
+    pub last_find: Option<Find>,
+    /// Set after `r`, the next key is the char to replace with.
+    pub pending_replace: bool,
+    /// Chars typed over since replace mode was entered, `None` for typed chars that were added.
+    /// Backspace puts them back.
+    pub replaced: Vec<Option<char>>,
+    /// Operator waiting for a motion, with the count typed before it.
+    pub operator: Option<(Operator, usize)>,
+    /// Messages of the last change with the count each was given, `.` runs them again.
+    pub last_change: Option<Vec<(Message, Option<usize>)>>,
+    /// The change being made, until normal mode is back.
+    pub pending_change: Option<Vec<(Message, Option<usize>)>>,
+    /// Set while `.` runs the last change, so it is not recorded again.
+    pub repeating: bool,
+    /// The next message was returned by the last one instead of coming from a key.
+    pub follow_up: bool,
+    pub state_file: Option<PathBuf>,
+    /// Last cursor position in files, by absolute path.
+    pub positions: BTreeMap<String, Position>,
+    /// Global marks from the state file in files that are not open yet.
+    pub file_marks: BTreeMap<char, FileMark>,
+    /// Orders jumplist entries across buffers.
+    pub jump_seq: u64,
+    /// Times swap files were written, the swap timer gets a new id after each try so a write
+    /// that failed is tried again.
+    pub swap_attempts: usize,
+    /// A failed swap write was reported, the next one is not until a write works again.
+    pub swap_error_shown: bool,
+    /// Entry of the jumplist `<C-o>` and `<C-i>` last went to, `None` after a new jump.
+    pub jump_idx: Option<usize>,
+    /// Text from the session opened with `revi -S`, for the `session_load` hook.
+    pub session_data: Option<String>,
     pub command: Buffer,
     pub map_keys: Mapper,
     pub key_parse: KeyParser,
+    /// Bumped on every key press so stale `timeoutlen` timers are ignored.
+    pub key_generation: usize,
+    /// Off while the keys of a `noremap` mapping are fed back.
+    pub remap: bool,
+    /// Number of mappings currently feeding keys.
+    pub map_depth: usize,
+    /// Set when mappings expanded past `MAX_MAP_DEPTH`.
+    pub map_aborted: bool,
+    pub options: Options,
+    /// Char index where the visual selection started.
+    pub visual_start: Option<usize>,
     pub mode: Mode,
     pub size: Size,
     pub is_running: bool,
@@ -70,6 +164,11 @@ impl State {
     }
 
     pub fn cursor_left(&mut self) -> Option<Message> {
+        if self.mode == Mode::Command {
+            let idx = self.command.cursor_char();
+            self.set_command_cursor(idx.saturating_sub(1));
+            return None;
+        }
         let buf = self.get_focused_buffer_mut();
         if buf.cursor_left() {
             return None;
@@ -78,6 +177,11 @@ impl State {
     }
 
     pub fn cursor_right(&mut self) -> Option<Message> {
+        if self.mode == Mode::Command {
+            let idx = self.command.cursor_char();
+            self.set_command_cursor(idx + 1);
+            return None;
+        }
         let width = self.size.width.saturating_sub(1) as usize;
         let buf = self.get_focused_buffer_mut();
         if buf.cursor_right(width) {
@@ -87,12 +191,20 @@ impl State {
     }
 
     pub fn cursor_home(&mut self) -> Option<Message> {
+        if self.mode == Mode::Command {
+            self.set_command_cursor(0);
+            return None;
+        }
         let buf = self.get_focused_buffer_mut();
         buf.cursor_home();
         None
     }
 
     pub fn cursor_end(&mut self) -> Option<Message> {
+        if self.mode == Mode::Command {
+            self.set_command_cursor(usize::MAX);
+            return None;
+        }
         let buf = self.get_focused_buffer_mut();
         buf.cursor_end();
         None
@@ -131,20 +243,113 @@ impl State {
         None
     }
 
+    /// `A`, insert mode with the cursor after the last char of the line.
     pub fn insert_at_end(&mut self) -> Option<Message> {
         self.change_mode(Mode::Insert);
-        let buf = self.get_focused_buffer_mut();
-        buf.cursor_end();
+        let size = self.text_size();
+        let buf = &mut self.buffers[self.focused];
+        let (line, _) = buf.line_col(buf.cursor_char());
+        let idx = buf.line_col_to_char(line, usize::MAX, true);
+        buf.set_cursor_char(idx, &size);
+        if let Some((start, ..)) = &mut self.insert {
+            *start = idx;
+        }
+        None
+    }
+
+    /// `o` and `O`, insert mode on a new line below or above the cursor line.
+    pub fn open_line(&mut self, below: bool) -> Option<Message> {
+        let autoindent = self.options.autoindent;
+        self.change_mode(Mode::Insert);
+        let size = self.text_size();
+        let buf = &mut self.buffers[self.focused];
+        let (line, _) = buf.line_col(buf.cursor_char());
+        let indent = match autoindent {
+            true => buf.indent(line),
+            false => String::new(),
+        };
+        let start = buf.open_line(line, below, &indent, &size);
+        if let Some(insert) = &mut self.insert {
+            *insert = (start, insert.1, true);
+        }
+        None
+    }
+
+    /// `J` joins count lines, at least two.
+    pub fn join_lines(&mut self) -> Option<Message> {
+        let count = self.take_count().max(2);
+        let size = self.text_size();
+        let buf = &mut self.buffers[self.focused];
+        let (line, _) = buf.line_col(buf.cursor_char());
+        buf.join_lines(line, count, &size);
+        None
+    }
+
+    /// `p` and `P` put the unnamed register count times, text ending in a newline goes on lines
+    /// of its own below or above the cursor line.
+    pub fn put(&mut self, after: bool) -> Option<Message> {
+        let count = self.take_count();
+        let text = self.registers.get(UNNAMED)?.repeat(count);
+        let size = self.text_size();
+        let buf = &mut self.buffers[self.focused];
+        if text.ends_with('\n') {
+            buf.put_lines(&text, after, &size);
+        } else {
+            buf.paste(&text, after, &size);
+            if !after {
+                // the cursor ends on the last put char
+                let idx = buf.cursor_char().saturating_sub(1);
+                buf.set_cursor_char(idx, &size);
+            }
+        }
         None
     }
 
     pub fn backspace(&mut self) -> Option<Message> {
+        if self.mode == Mode::Command {
+            if self.command.get_rope().len_chars() == 0 {
+                return self.change_mode(Mode::Normal);
+            }
+            let idx = self.command.cursor_char();
+            let size = self.command_size();
+            self.command.remove(idx.saturating_sub(1)..idx, &size);
+            return None;
+        }
+        if self.mode == Mode::Replace {
+            // before the typed text backspace only moves the cursor
+            let Some(original) = self.replaced.pop() else {
+                return self.cursor_left();
+            };
+            let size = self.text_size();
+            self.buffers[self.focused].restore(original, &size);
+            return None;
+        }
         let buf = self.get_focused_buffer_mut();
         buf.backspace();
         None
     }
 
     pub fn delete_char(&mut self) -> Option<Message> {
+        if self.mode == Mode::Normal {
+            // `x` deletes count chars up to the end of the line
+            let count = self.take_count();
+            let size = self.text_size();
+            let buf = &mut self.buffers[self.focused];
+            let start = buf.cursor_char();
+            let rope = buf.get_rope();
+            let len = rope
+                .chars_at(start.min(rope.len_chars()))
+                .take(count)
+                .take_while(|c| *c != '\n')
+                .count();
+            if len > 0 {
+                let text = rope.slice(start..start + len).to_string();
+                buf.remove(start..start + len, &size);
+                self.registers.set(UNNAMED, &text);
+                self.go_to_char(self.focused, start);
+            }
+            return None;
+        }
         let buf = self.get_focused_buffer_mut();
         buf.delete_char();
         None
@@ -156,38 +361,278 @@ impl State {
     }
 
     pub fn key_press(&mut self, keys: Keys) -> Option<Message> {
+        // Keys fed by mappings and macros are not recorded, the keys that started them are.
+        let typed = self.map_depth == 0;
+        let starts_command = self.key_parse.get_keys().is_empty()
+            && matches!(
+                self.mode,
+                Mode::Normal | Mode::Visual | Mode::OperatorPending
+            )
+            && !self.pending_register
+            && self.pending_find.is_none()
+            && !self.pending_replace;
+        if let Some((_, recorded)) = self.recording.as_mut().filter(|_| typed) {
+            if starts_command && keys.as_char() == Some('q') {
+                return self.stop_recording();
+            }
+            recorded.push(keys);
+        }
+        if std::mem::take(&mut self.pending_register) {
+            return self.insert_register(keys);
+        }
+        if let Some((forward, till)) = self.pending_find.take() {
+            return self.find_key(keys, forward, till);
+        }
+        if std::mem::take(&mut self.pending_replace) {
+            return self.replace_key(keys);
+        }
+        let digit = keys.as_char().and_then(|c| c.to_digit(10));
+        if let Some(digit) =
+            digit.filter(|d| starts_command && (*d != 0 || self.pending_count.is_some()))
+        {
+            let count = self.pending_count.unwrap_or(0);
+            self.pending_count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+            return None;
+        }
+        self.key_generation = self.key_generation.wrapping_add(1);
         self.key_parse.push(keys);
         Some(Message::CheckForMapping)
     }
 
+    /// Size of the area buffers are drawn in, the status bar and command line take the rest.
+    pub fn text_size(&self) -> Size {
+        Size {
+            width: self.size.width,
+            height: self.size.height.saturating_sub(2),
+        }
+    }
+
+    /// Inserts pasted text where the mode would type it.
+    /// Normal mode pastes after the cursor like `p`, the command line gets a single line.
+    pub fn paste(&mut self, text: &str) -> Option<Message> {
+        // Keys typed before the paste come first.
+        self.flush_pending_keys();
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let size = self.text_size();
+        match self.mode {
+            Mode::Insert => self.buffers[self.focused].paste(&text, false, &size),
+            Mode::Replace => {
+                let replaced = self.buffers[self.focused].overwrite(&text, &size);
+                self.replaced.extend(replaced);
+            }
+            Mode::Normal | Mode::Visual | Mode::OperatorPending => {
+                self.buffers[self.focused].paste(&text, true, &size)
+            }
+            Mode::Command => {
+                let size = self.command_size();
+                self.command.paste(&text.replace('\n', " "), false, &size);
+            }
+        }
+        None
+    }
+
     pub fn insert_mode_insert(&mut self, c: impl Into<String>) -> Option<Message> {
         self.buffers[self.focused].insert(c);
         None
     }
 
+    pub fn replace_mode_insert(&mut self, text: &str) -> Option<Message> {
+        let size = self.text_size();
+        let replaced = self.buffers[self.focused].overwrite(text, &size);
+        self.replaced.extend(replaced);
+        None
+    }
+
+    /// The char typed after `r`, other keys cancel it.
+    fn replace_key(&mut self, keys: Keys) -> Option<Message> {
+        let Some(c) = keys.as_char().filter(|c| !matches!(c, '\0' | '\u{1b}')) else {
+            return self.cancel_motion();
+        };
+        Some(Message::Replace(c))
+    }
+
+    /// `r{char}` replaces count chars under the cursor, it fails when the line is too short.
+    pub fn replace(&mut self, c: char) -> Option<Message> {
+        let count = self.take_count();
+        let size = self.text_size();
+        if !self.buffers[self.focused].replace_chars(c, count, &size) {
+            // nothing changed for `.` to repeat
+            self.pending_change = None;
+        }
+        None
+    }
+
     pub fn change_mode(&mut self, mode: Mode) -> Option<Message> {
         if let Mode::Command = self.mode {
             self.get_focused_buffer_mut().clear();
+            self.completion = None;
+            self.history.reset();
+            self.pending_register = false;
+        }
+        // leaving operator pending mode without a motion drops the operator and its change
+        if self.operator.take().is_some() {
+            self.pending_change = None;
+        }
+        let typing = |mode| matches!(mode, Mode::Insert | Mode::Replace);
+        if !typing(self.mode) && typing(mode) {
+            let start = self.buffers[self.focused].cursor_char();
+            self.insert = Some((start, self.take_count(), false));
+            self.replaced.clear();
+        }
+        if typing(self.mode) && !typing(mode) {
+            self.repeat_insert();
+            let buf = &mut self.buffers[self.focused];
+            let idx = buf.cursor_char();
+            buf.marks.set(LAST_INSERT, idx);
+            // the cursor steps back onto the last typed char
+            if buf.line_col(idx).1 > 0 {
+                self.go_to_char(self.focused, idx - 1);
+            }
         }
         self.get_focused_buffer_mut().align_cursor();
         self.mode = mode;
+        self.visual_start = match mode {
+            Mode::Visual => self
+                .visual_start
+                .or(Some(self.buffers[self.focused].cursor_char())),
+            _ => None,
+        };
         None
     }
 
-    pub fn execute_command(&mut self) -> Option<Message> {
-        let size = Size {
-            height: 2,
-            width: self.size.width,
+    /// A count before entering insert mode types the inserted text that many times,
+    /// replace mode types it over the text after it.
+    fn repeat_insert(&mut self) {
+        let Some((start, count, lines)) = self.insert.take() else {
+            return;
         };
-        let command = self
-            .command
-            .on_screen(&size)
-            .iter()
-            .map(ToString::to_string)
-            .collect::<String>()
-            .trim()
-            .to_string();
+        let size = self.text_size();
+        let buf = &mut self.buffers[self.focused];
+        let end = buf.cursor_char();
+        if count > 1 && end > start {
+            let mut text = buf.get_rope().slice(start..end).to_string();
+            if lines {
+                text.insert(0, '\n');
+            }
+            let copies = count - 1;
+            if text.len().saturating_mul(copies) > MAX_REPEAT_LEN {
+                return;
+            }
+            match self.mode {
+                Mode::Replace => {
+                    buf.overwrite(&text.repeat(copies), &size);
+                }
+                _ => buf.paste(&text.repeat(copies), false, &size),
+            }
+        }
+    }
+
+    /// First and last char index of the visual selection.
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        let start = self.visual_start?;
+        let cursor = self.buffers[self.focused].cursor_char();
+        Some((start.min(cursor), start.max(cursor)))
+    }
+
+    /// Clicks put the cursor in the text area, dragging selects in visual mode
+    /// and the wheel scrolls. Clicking the status bar lists the buffers.
+    pub fn mouse(&mut self, event: MouseEvent) -> Option<Message> {
+        let text_size = self.text_size();
+        let pos = Pos::new(event.column, event.row);
+        match event.kind {
+            MouseEventKind::ScrollUp => {
+                for _ in 0..WHEEL_LINES {
+                    self.scroll_up();
+                }
+                None
+            }
+            MouseEventKind::ScrollDown => {
+                for _ in 0..WHEEL_LINES {
+                    self.scroll_down();
+                }
+                None
+            }
+            MouseEventKind::Down(MouseButton::Left) if pos.y < text_size.height => {
+                if matches!(
+                    self.mode,
+                    Mode::Command | Mode::Visual | Mode::OperatorPending
+                ) {
+                    self.change_mode(Mode::Normal);
+                }
+                self.click_text(pos);
+                None
+            }
+            MouseEventKind::Down(MouseButton::Left) if pos.y == text_size.height => {
+                Some(Message::BufferList)
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                match self.mode {
+                    Mode::Command => return None,
+                    Mode::Visual => {}
+                    // The selection starts where the button went down.
+                    Mode::Normal | Mode::Insert | Mode::Replace | Mode::OperatorPending => {
+                        self.change_mode(Mode::Visual);
+                    }
+                }
+                let row = pos.y.min(text_size.height.saturating_sub(1));
+                self.click_text(Pos::new(pos.x, row));
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Moves the cursor to the character shown at `pos` in the text area.
+    fn click_text(&mut self, pos: Pos) {
+        let size = self.text_size();
+        let past_end = matches!(self.mode, Mode::Insert | Mode::Replace);
+        let buf = &mut self.buffers[self.focused];
+        let idx = buf.char_at(pos, past_end);
+        buf.set_cursor_char(idx, &size);
+    }
+
+    /// A line of the text area, with the part inside the visual selection highlighted.
+    /// `row` is the row on screen.
+    fn text_line(&self, line: &str, row: usize, width: u16) -> BoxWidget {
+        let buf = &self.buffers[self.focused];
+        let cursor = buf.get_cursor();
+        let row = cursor.scroll.y as usize + row;
+        let selected = self.selection().filter(|_| row <= buf.last_line());
+        let Some((start, end)) = selected else {
+            return Text::new(line).max_width(width).into();
+        };
+        let line_start = buf.get_rope().line_to_char(row) + cursor.scroll.x as usize;
+        let len = line.chars().count();
+        let from = start.saturating_sub(line_start).min(len);
+        let to = (end + 1).saturating_sub(line_start).min(len);
+        if from >= to {
+            return Text::new(line).max_width(width).into();
+        }
+        let part = |a: usize, b: usize| line.chars().skip(a).take(b - a).collect::<String>();
+        let rect = Rect::new(Size::new(width, 1));
+        let mut container = Container::new(rect, Stack::Horizontally);
+        if from > 0 {
+            container = container.push(Text::new(&part(0, from)).max_width(from as u16));
+        }
+        container = container.push(
+            Text::new(&part(from, to))
+                .max_width((to - from) as u16)
+                .with_fg(Color::Black)
+                .with_bg(Color::Grey),
+        );
+        let rest = width.saturating_sub(to as u16);
+        container
+            .push(Text::new(&part(to, len)).max_width(rest))
+            .into()
+    }
+
+    pub fn execute_command(&mut self) -> Option<Message> {
+        let command = self.command.get_all_text().trim().to_string();
         self.command = Buffer::default();
+        if !command.is_empty() {
+            self.history.add(&command, self.options.history as usize);
+            self.registers.set(LAST_COMMAND, &command);
+        }
         let (cmd, tail) = command.split_once(' ').unwrap_or((command.as_str(), ""));
         self.change_mode(Mode::Normal);
         match cmd {
@@ -202,13 +647,24 @@ impl State {
             "ls" => Some(Message::BufferList),
             "edit" | "e" => Some(Message::EditFile(tail.to_string())),
             "buffer" | "b" => Some(Message::SwapBuffer(tail.to_string())),
-            _ => Some(
-                UserMessageBuilder::default()
-                    .message(command)
-                    .footer("UnKnown Command")
-                    .fg(Color::Red)
-                    .build(),
-            ),
+            "set" => Some(Message::SetOption(tail.to_string())),
+            "checktime" => Some(Message::CheckTime),
+            "let" => self.let_command(tail),
+            "marks" => Some(Message::ListMarks),
+            "jumps" | "ju" => Some(Message::ListJumps),
+            "mksession" | "mks" => Some(Message::MakeSession(tail.to_string(), false, None)),
+            "mksession!" | "mks!" => Some(Message::MakeSession(tail.to_string(), true, None)),
+            "suspend" | "stop" => Some(Message::Suspend),
+            _ => match MapCommand::parse(cmd) {
+                Some(map) => Some(Message::Map(map, tail.to_string())),
+                None => Some(
+                    UserMessageBuilder::default()
+                        .message(command)
+                        .footer("UnKnown Command")
+                        .fg(Color::Red)
+                        .build(),
+                ),
+            },
         }
     }
 
@@ -230,8 +686,12 @@ impl State {
     }
 
     pub fn edit_file_command(&mut self, filename: &str) -> Option<Message> {
+        self.record_jump();
         let buf = Buffer::from_path(filename);
         self.set_new_buffer_as_focused(buf);
+        self.restore_position(self.focused);
+        self.restore_file_marks(self.focused);
+        self.check_swap_file(filename);
         None
     }
 
@@ -248,6 +708,7 @@ impl State {
                 //     .call(ctx.clone());
                 // return;
             };
+            self.record_jump();
             self.focused = idx;
             return None;
         }
@@ -265,6 +726,7 @@ impl State {
             //     .call(ctx.clone());
             // return;
         };
+        self.record_jump();
         self.focused = idx;
         None
     }
@@ -274,29 +736,44 @@ impl State {
         None
     }
 
-    pub fn next_available_command(&mut self) -> Option<Message> {
-        let current_cmd = self.get_focused_buffer().get_all_text();
-        let list = if current_cmd.is_empty() {
-            self.command_list.get_all_words()
-        } else {
-            self.command_list
-                .lookup(&current_cmd)
+    /// Replaces the word before the cursor on the command line with the next or previous completion.
+    /// A single candidate is taken right away, otherwise `wildmenu` lists them.
+    pub fn next_available_command(&mut self, forward: bool) -> Option<Message> {
+        let line = self.command.get_all_text();
+        let editing = self.completion.as_ref().filter(|c| c.line() == line);
+        if editing.is_none() {
+            let commands = self
+                .command_list
+                .get_all_words()
                 .into_iter()
-                .filter(|word| word != &current_cmd)
-                .collect::<Vec<_>>()
-        };
-        let Some(cmd) = list.get(self.tab_index) else {
-            return None;
-        };
-        self.tab_index = (self.tab_index + 1) % list.len();
-        let buf = self.get_focused_buffer_mut();
-        buf.clear();
-        buf.insert(cmd);
+                .chain(MapCommand::NAMES.iter().map(ToString::to_string))
+                .collect::<Vec<_>>();
+            let buffers = self
+                .buffers
+                .iter()
+                .filter(|b| !b.is_scratch())
+                .map(|b| b.name.clone())
+                .collect::<Vec<_>>();
+            let fuzzy = self.options.wildfuzzy;
+            self.completion = Some(Completion::new(&line, &commands, &buffers, fuzzy));
+        }
+        let completion = self.completion.as_mut()?;
+        if forward {
+            completion.next();
+        } else {
+            completion.prev();
+        }
+        let line = completion.line();
+        if completion.items.len() < 2 {
+            self.completion = None;
+        }
+        self.set_command_line(&line);
         None
     }
 
     pub fn save(&mut self, filename: Option<String>) -> Option<Message> {
-        if let Err(err) = self.get_focused_buffer().save(filename) {
+        let buf = self.get_focused_buffer_mut();
+        if let Err(err) = buf.save(filename) {
             return Some(
                 UserMessageBuilder::default()
                     .message(err.to_string())
@@ -305,127 +782,1549 @@ impl State {
                     .build(),
             );
         }
+        if !buf.modified {
+            SwapFile::remove(&buf.name);
+            buf.unswapped_changes = 0;
+        }
         None
     }
 
-    pub fn command_mode_insert(&mut self, c: impl Into<String>) -> Option<Message> {
-        self.command.insert(c);
-        None
+    /// Asks the user what to do with a swap file left behind by a revi that is no longer running.
+    pub fn check_swap_file(&mut self, name: &str) {
+        let Some(swap) = SwapFile::find(name) else {
+            return;
+        };
+        if swap.is_ours() {
+            return;
+        }
+        if swap.is_running() {
+            self.messages.push(
+                UserMessageBuilder::default()
+                    .message(format!(
+                        "\"{}\" is being edited by another revi (process {})",
+                        swap.file, swap.pid
+                    ))
+                    .footer("swap file")
+                    .fg(Color::Yellow),
+            );
+            return;
+        }
+        let builder = UserMessageBuilder::default()
+            .message(format!(
+                "Found a swap file for \"{}\" left by process {}",
+                swap.file, swap.pid
+            ))
+            .footer("[R]ecover, [D]elete swap file, any other key to ignore")
+            .fg(Color::Yellow);
+        let prompt = Prompt::new(builder)
+            .choice('r', Message::RecoverSwap(name.to_string()))
+            .choice('d', Message::DeleteSwap(name.to_string()));
+        self.prompts.push(prompt);
     }
 
-    pub fn check_for_mapping(&mut self) -> Option<Message> {
-        let msg = self
-            .map_keys
-            .get_mapping(&self.mode, self.key_parse.get_keys());
-        let is_possible_mapping = self
-            .map_keys
-            .is_possible_mapping(&self.mode, self.key_parse.get_keys());
-        if !is_possible_mapping {
-            let key_list = self.key_parse.get_keys();
-            let input = key_list
-                .iter()
-                .filter_map(|k| {
-                    k.as_char().and_then(|c| match c {
-                        '\0' => None,
-                        _ => Some(c),
-                    })
-                })
-                .collect::<Vec<char>>()
-                .iter()
-                .map(ToString::to_string)
-                .collect::<Vec<String>>()
-                .join("");
-            self.key_parse.clear();
-            let message = match self.mode {
-                Mode::Command => Message::ModeCommandInsertStr(input),
-                Mode::Insert => Message::ModeInsertInsertStr(input),
-                _ => return None,
+    pub fn prompt_key(&mut self, keys: Keys) -> Option<Message> {
+        if keys.is_null() {
+            return None;
+        }
+        let prompt = self.prompts.pop()?;
+        keys.as_char().and_then(|c| prompt.answer(c))
+    }
+
+    /// Takes history, registers and cursor positions from earlier sessions.
+    fn load_state_file(&mut self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let saved = StateFile::read(path);
+        self.history.set_entries(saved.history);
+        for (name, (text, time)) in saved.registers {
+            self.registers.restore(name, &text, time);
+        }
+        self.positions = saved.positions;
+        self.file_marks = saved.marks;
+    }
+
+    /// Merges history, registers, global marks and the cursor position in each open file into
+    /// the state file.
+    pub fn write_state_file(&mut self) -> std::io::Result<()> {
+        let Some(path) = self.state_file.clone() else {
+            return Ok(());
+        };
+        for buf in self.buffers.iter().filter(|b| !b.is_scratch()) {
+            let pos = buf.get_cursor().pos();
+            let position = Position {
+                line: pos.y as usize,
+                col: pos.x as usize,
+                time: state_file::now(),
             };
-            return Some(message);
+            self.positions.insert(absolute_name(&buf.name), position);
         }
-        if msg.is_some() {
-            self.key_parse.clear();
+        let mut marks = self.file_marks.clone();
+        for buf in self.buffers.iter().filter(|b| !b.is_scratch()) {
+            let global = buf
+                .marks
+                .iter()
+                .filter(|(name, _)| name.is_ascii_uppercase());
+            for (name, idx) in global {
+                let (line, col) = buf.line_col(idx);
+                let mark = FileMark {
+                    path: absolute_name(&buf.name),
+                    line,
+                    col,
+                    time: state_file::now(),
+                };
+                marks.insert(name, mark);
+            }
+        }
+        let state = StateFile {
+            history: self.history.entries().to_vec(),
+            registers: self
+                .registers
+                .iter()
+                .map(|(name, text, time)| (name, (text.to_string(), time)))
+                .collect(),
+            positions: self.positions.clone(),
+            marks,
+        };
+        state.write(&path, self.options.history as usize)
+    }
+
+    /// Puts the cursor of buffer `idx` where it was when its file was last left.
+    fn restore_position(&mut self, idx: usize) {
+        let size = self.text_size();
+        let buf = &mut self.buffers[idx];
+        if buf.is_scratch() {
+            return;
         }
-        msg
+        let Some(position) = self.positions.get(&absolute_name(&buf.name)) else {
+            return;
+        };
+        let idx = buf.line_col_to_char(position.line, position.col, false);
+        buf.set_cursor_char(idx, &size);
     }
-}
 
-impl App for State {
-    type Settings = Settings;
-    type Message = Message;
+    /// Sets the global marks the state file has for the file of buffer `idx`.
+    fn restore_file_marks(&mut self, idx: usize) {
+        let buf = &mut self.buffers[idx];
+        if buf.is_scratch() {
+            return;
+        }
+        let path = absolute_name(&buf.name);
+        let names = self
+            .file_marks
+            .iter()
+            .filter(|(_, mark)| mark.path == path)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        for name in names {
+            let Some(mark) = self.file_marks.remove(&name) else {
+                continue;
+            };
+            let idx = buf.line_col_to_char(mark.line, mark.col, false);
+            buf.marks.set(name, idx);
+        }
+    }
 
-    fn new(settings: Self::Settings) -> Self {
-        let buffers = if settings.buffers.is_empty() {
-            vec![Buffer::default()]
+    /// Writes the open files, their views, the focused buffer and the changed options to the
+    /// session file `name`, `Session.revi` when it is empty.
+    pub fn make_session(
+        &mut self,
+        name: &str,
+        force: bool,
+        data: Option<String>,
+    ) -> Option<Message> {
+        let name = if name.is_empty() {
+            session::DEFAULT_NAME
         } else {
-            settings.buffers
+            name
         };
-        Self {
-            focused: 0,
+        let files = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| !b.is_scratch())
+            .collect::<Vec<_>>();
+        let buffers = files
+            .iter()
+            .map(|(_, b)| {
+                let cursor = b.get_cursor();
+                let pos = cursor.pos();
+                SessionBuffer {
+                    path: absolute_name(&b.name),
+                    line: pos.y as usize,
+                    col: pos.x as usize,
+                    scroll_line: cursor.scroll.y as usize,
+                    scroll_col: cursor.scroll.x as usize,
+                }
+            })
+            .collect();
+        let session = Session {
             buffers,
-            messages: Vec::new(),
-            command: Buffer::default(),
-            command_list: trie::Trie::from(&vec![
-                "b", "buffer", "ls", "e", "edit", "q", "quit", "w", "write",
-            ]),
-            tab_index: 0,
-            map_keys: Mapper::default(),
-            key_parse: KeyParser::default(),
-            mode: Mode::Normal,
-            size: size(),
-            is_running: true,
+            focused: files
+                .iter()
+                .position(|(idx, _)| *idx == self.focused)
+                .unwrap_or_default(),
+            options: self.options.changed(),
+            data,
+        };
+        let footer = match session.write(name.as_ref(), force) {
+            Ok(()) => return None,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                "File exists, add ! to overwrite".to_string()
+            }
+            Err(err) => err.to_string(),
+        };
+        Some(
+            UserMessageBuilder::default()
+                .message(name)
+                .footer(footer)
+                .fg(Color::Red)
+                .build(),
+        )
+    }
+
+    /// Opens the files of the session at `path` with their views and options.
+    /// A lone empty buffer is replaced, files that are already open are reused.
+    fn load_session(&mut self, path: &std::path::Path) -> Result<(), String> {
+        let session = Session::read(path)?;
+        for option in &session.options {
+            self.options.set(option)?;
+        }
+        if self.buffers.len() == 1 && self.buffers[0].is_scratch() && !self.buffers[0].modified {
+            self.buffers.clear();
+        }
+        let size = self.text_size();
+        let mut opened = Vec::new();
+        for saved in &session.buffers {
+            let idx = match self
+                .buffers
+                .iter()
+                .position(|b| !b.is_scratch() && absolute_name(&b.name) == saved.path)
+            {
+                Some(idx) => idx,
+                None => {
+                    self.buffers.push(Buffer::from_path(&saved.path));
+                    self.check_swap_file(&saved.path);
+                    self.restore_file_marks(self.buffers.len() - 1);
+                    self.buffers.len() - 1
+                }
+            };
+            let buf = &mut self.buffers[idx];
+            buf.set_scroll(saved.scroll_line, saved.scroll_col);
+            let cursor = buf.line_col_to_char(saved.line, saved.col, false);
+            buf.set_cursor_char(cursor, &size);
+            opened.push(idx);
         }
+        if self.buffers.is_empty() {
+            self.buffers.push(Buffer::default());
+        }
+        self.focused = opened.get(session.focused).copied().unwrap_or_default();
+        self.session_data = session.data;
+        Ok(())
     }
 
-    fn view(&self) -> revi_ui::widget::BoxWidget {
-        let Size { width, height } = self.size;
-        let rect = Rect::new(self.size);
+    /// Puts the cursor of buffer `buf` on char index `idx`, or on the last char of its line
+    /// when `idx` is a newline.
+    fn go_to_char(&mut self, buf: usize, idx: usize) {
+        let size = self.text_size();
+        let buf = &mut self.buffers[buf];
+        let (line, col) = buf.line_col(idx);
+        let idx = buf.line_col_to_char(line, col, false);
+        buf.set_cursor_char(idx, &size);
+    }
 
-        if let Some(builder) = self.messages.last() {
-            return builder.as_view(rect).into();
-        }
+    /// Remembers the cursor position in the jumplist and the context mark before a jump.
+    fn record_jump(&mut self) {
+        self.jump_seq += 1;
+        self.jump_idx = None;
+        self.buffers[self.focused].add_jump(self.jump_seq);
+        self.set_context_mark();
+    }
 
-        let buf = &self.buffers[self.focused];
-        // ------ TEXT AREA --------
-        let text_size = Size {
-            width,
-            height: height - 2,
-        };
-        let rect_text = Rect::new(text_size);
-        // let mut p = tree_sitter::Parser::new();
-        // p.set_language(tree_sitter_md::language())
-        //     .expect("Error loading Rust grammar");
+    fn set_context_mark(&mut self) {
+        let buf = &mut self.buffers[self.focused];
+        buf.marks.set(CONTEXT, buf.cursor_char());
+    }
 
-        // let mut last_context_parser: Option<tree_sitter::Tree> = None;
-        let text = buf
-            .on_screen(&text_size)
+    /// Every jumplist entry as buffer and char index, oldest first.
+    fn jumplist(&self) -> Vec<(usize, usize)> {
+        let mut jumps = self
+            .buffers
             .iter()
-            .map(|line| {
-                Text::new(line.as_str()).max_width(width)
-                // last_context_parser = p.parse(line, last_context_parser.as_ref());
-                // let mut text_line =
-                //     Container::new(Rect::new(Size::new(width, 1)), Stack::Horizontally);
-                // if let Some(tree) = &last_context_parser {
-                //     let mut cursor = tree.walk();
-                //     let mut node = cursor.node();
-                //     for n in node.children(&mut cursor) {
-                //         let t = Text::new(n.kind()).max_width(width);
-                //         text_line = text_line.push(t);
-                //     }
-                // } else {
-                //     let t = Text::new(line.as_str()).max_width(width);
-                //     text_line = text_line.push(t);
-                // }
-                // text_line
+            .enumerate()
+            .flat_map(|(buf, b)| {
+                b.marks
+                    .jumps()
+                    .iter()
+                    .map(move |(seq, idx)| (*seq, buf, *idx))
             })
-            .chain(std::iter::repeat(Text::new(" ").max_width(width)))
-            // .chain(std::iter::repeat(
-            //     Container::new(Rect::new(Size::new(width, 1)), Stack::Horizontally)
-            //         .push(Text::new(" ").max_width(width)),
-            // ))
-            .take(height as usize)
-            .fold(Container::new(rect_text, Stack::Vertically), |acc, item| {
-                acc.push(item)
+            .collect::<Vec<_>>();
+        jumps.sort_unstable();
+        jumps.into_iter().map(|(_, buf, idx)| (buf, idx)).collect()
+    }
+
+    /// `gg` and `G`, the cursor goes to the first non-blank char of `line`.
+    pub fn jump_to_line(&mut self, line: usize) -> Option<Message> {
+        self.record_jump();
+        let idx = self.buffers[self.focused].first_non_blank(line);
+        self.go_to_char(self.focused, idx);
+        None
+    }
+
+    pub fn set_mark(&mut self, name: char) -> Option<Message> {
+        let idx = self.buffers[self.focused].cursor_char();
+        if name.is_ascii_uppercase() {
+            for buf in &mut self.buffers {
+                buf.marks.remove(name);
+            }
+            self.file_marks.remove(&name);
+        }
+        self.buffers[self.focused].marks.set(name, idx);
+        None
+    }
+
+    /// `` `a `` goes to mark `a`, `'a` to the first non-blank char of its line when not `exact`.
+    /// Uppercase marks switch to the buffer that has them, and open the file of one saved in
+    /// the state file.
+    pub fn go_to_mark(&mut self, name: char, exact: bool) -> Option<Message> {
+        let found = if name.is_ascii_uppercase() {
+            self.buffers
+                .iter()
+                .enumerate()
+                .find_map(|(buf, b)| Some((buf, b.marks.get(name)?)))
+                .or_else(|| self.open_file_mark(name))
+        } else {
+            let buf = self.focused;
+            self.buffers[buf].marks.get(name).map(|idx| (buf, idx))
+        };
+        let Some((buf, idx)) = found else {
+            return Some(
+                UserMessageBuilder::default()
+                    .message(format!("Mark not set: {name}"))
+                    .footer("Marks")
+                    .fg(Color::Red)
+                    .build(),
+            );
+        };
+        self.record_jump();
+        self.focused = buf;
+        let idx = if exact {
+            idx
+        } else {
+            let buf = &self.buffers[buf];
+            buf.first_non_blank(buf.line_col(idx).0)
+        };
+        self.go_to_char(buf, idx);
+        None
+    }
+
+    /// Opens the file of global mark `name` from the state file, returns the new buffer and
+    /// the char index of the mark.
+    fn open_file_mark(&mut self, name: char) -> Option<(usize, usize)> {
+        let path = self.file_marks.get(&name)?.path.clone();
+        self.buffers.push(Buffer::from_path(&path));
+        let idx = self.buffers.len() - 1;
+        self.check_swap_file(&path);
+        self.restore_position(idx);
+        self.restore_file_marks(idx);
+        Some((idx, self.buffers[idx].marks.get(name)?))
+    }
+
+    /// `<C-o>`, the first time the cursor position is added so `<C-i>` can come back to it.
+    pub fn jump_older(&mut self) -> Option<Message> {
+        let current = match self.jump_idx {
+            Some(current) => current,
+            None => {
+                self.record_jump();
+                self.jumplist().len() - 1
+            }
+        };
+        let target = current.checked_sub(1)?;
+        let (buf, idx) = self.jumplist()[target];
+        self.set_context_mark();
+        self.focused = buf;
+        self.go_to_char(buf, idx);
+        self.jump_idx = Some(target);
+        None
+    }
+
+    /// `<C-i>`, back towards the newest entry of the jumplist.
+    pub fn jump_newer(&mut self) -> Option<Message> {
+        let target = self.jump_idx? + 1;
+        let (buf, idx) = *self.jumplist().get(target)?;
+        self.set_context_mark();
+        self.focused = buf;
+        self.go_to_char(buf, idx);
+        self.jump_idx = Some(target);
+        None
+    }
+
+    /// `g;`, to the position of an older change.
+    pub fn change_older(&mut self) -> Option<Message> {
+        let marks = &self.buffers[self.focused].marks;
+        let current = marks.change_idx.unwrap_or(marks.changes().len());
+        self.go_to_change(current.checked_sub(1)?)
+    }
+
+    /// `g,`, to the position of a newer change.
+    pub fn change_newer(&mut self) -> Option<Message> {
+        let current = self.buffers[self.focused].marks.change_idx?;
+        self.go_to_change(current + 1)
+    }
+
+    fn go_to_change(&mut self, target: usize) -> Option<Message> {
+        let buf = &mut self.buffers[self.focused];
+        let idx = *buf.marks.changes().get(target)?;
+        buf.marks.change_idx = Some(target);
+        self.go_to_char(self.focused, idx);
+        None
+    }
+
+    /// Line, column and text or file name of char index `idx` in buffer `buf` for `:marks` and `:jumps`.
+    fn describe_position(&self, buf: usize, idx: usize) -> String {
+        let b = &self.buffers[buf];
+        let (line, col) = b.line_col(idx);
+        let text = if buf == self.focused {
+            b.line_text(line).trim().to_string()
+        } else {
+            b.name.clone()
+        };
+        format!("{:>5} {:>4} {text}", line + 1, col)
+    }
+
+    pub fn list_marks(&mut self) -> Option<Message> {
+        let mut lines = vec!["mark line  col file/text".to_string()];
+        let local = self.buffers[self.focused]
+            .marks
+            .iter()
+            .filter(|(name, _)| !name.is_ascii_uppercase())
+            .map(|(name, idx)| (name, self.focused, idx));
+        let global = self.buffers.iter().enumerate().flat_map(|(buf, b)| {
+            b.marks
+                .iter()
+                .filter(|(name, _)| name.is_ascii_uppercase())
+                .map(move |(name, idx)| (name, buf, idx))
+        });
+        let mut marks = local.chain(global).collect::<Vec<_>>();
+        marks.sort_by_key(|(name, ..)| (name.is_ascii_alphabetic(), *name));
+        for (name, buf, idx) in marks {
+            lines.push(format!(" {name} {}", self.describe_position(buf, idx)));
+        }
+        Some(
+            UserMessageBuilder::default()
+                .message(lines.join("\n"))
+                .footer("marks")
+                .build(),
+        )
+    }
+
+    pub fn list_jumps(&mut self) -> Option<Message> {
+        let jumps = self.jumplist();
+        let current = self.jump_idx.unwrap_or(jumps.len());
+        let mut lines = vec![" jump line  col file/text".to_string()];
+        for (i, (buf, idx)) in jumps.into_iter().enumerate() {
+            let marker = if i == current { '>' } else { ' ' };
+            lines.push(format!(
+                "{marker}{:>4} {}",
+                i.abs_diff(current),
+                self.describe_position(buf, idx)
+            ));
+        }
+        if self.jump_idx.is_none() {
+            lines.push(">".to_string());
+        }
+        Some(
+            UserMessageBuilder::default()
+                .message(lines.join("\n"))
+                .footer("jumps")
+                .build(),
+        )
+    }
+
+    /// Offers to reload buffers whose file another program changed,
+    /// with `autoread` buffers without changes of their own are reloaded right away.
+    pub fn check_time(&mut self) -> Option<Message> {
+        let names = self
+            .buffers
+            .iter()
+            .map(|b| b.name.clone())
+            .collect::<Vec<_>>();
+        self.check_files(&names, self.options.autoread)
+    }
+
+    /// Looks at the buffers for `names` and offers to reload the ones whose file changed on disk.
+    /// With `reload` buffers without changes of their own are reloaded without asking.
+    pub fn check_files(&mut self, names: &[String], reload: bool) -> Option<Message> {
+        let changed = self
+            .buffers
+            .iter_mut()
+            .filter(|b| names.contains(&b.name))
+            .filter_map(|b| b.changed_on_disk().then(|| (b.name.clone(), b.modified)))
+            .collect::<Vec<_>>();
+        for (name, modified) in changed {
+            if reload && !modified {
+                // a file that fails to load must not keep the others from reloading
+                if let Some(Message::UserMessage(builder)) = self.reload_buffer(&name) {
+                    self.messages.push(builder);
+                }
+                continue;
+            }
+            let message = if modified {
+                format!("\"{name}\" changed on disk and the buffer has unsaved changes")
+            } else {
+                format!("\"{name}\" changed on disk")
+            };
+            let builder = UserMessageBuilder::default()
+                .message(message)
+                .footer("[L]oad file, any other key to keep the buffer")
+                .fg(Color::Yellow);
+            let prompt = Prompt::new(builder).choice('l', Message::ReloadBuffer(name));
+            self.prompts.push(prompt);
+        }
+        None
+    }
+
+    pub fn reload_buffer(&mut self, name: &str) -> Option<Message> {
+        let size = self.text_size();
+        let buf = self.buffers.iter_mut().find(|b| b.name == name)?;
+        if let Err(err) = buf.reload(&size) {
+            return Some(
+                UserMessageBuilder::default()
+                    .message(err.to_string())
+                    .fg(Color::Red)
+                    .footer("reload")
+                    .build(),
+            );
+        }
+        SwapFile::remove(name);
+        None
+    }
+
+    pub fn recover_swap(&mut self, name: &str) -> Option<Message> {
+        let buf = self.buffers.iter_mut().find(|b| b.name == name)?;
+        let recovered = SwapFile::find(name)
+            .ok_or_else(|| "swap file no longer exists".to_string())
+            .and_then(|swap| swap.contents().map_err(|err| err.to_string()));
+        match recovered {
+            Ok(text) => {
+                buf.recover(&text);
+                None
+            }
+            Err(err) => Some(
+                UserMessageBuilder::default()
+                    .message(err)
+                    .fg(Color::Red)
+                    .footer("recover")
+                    .build(),
+            ),
+        }
+    }
+
+    pub fn delete_swap(&mut self, name: &str) -> Option<Message> {
+        SwapFile::remove(name);
+        None
+    }
+
+    /// Writes swap files for buffers with at least `min_changes` edits that are not on disk yet.
+    fn write_swap_files(&mut self, min_changes: usize) -> Option<Message> {
+        let pending = self
+            .buffers
+            .iter_mut()
+            .filter(|b| !b.is_scratch() && b.unswapped_changes >= min_changes.max(1))
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            return None;
+        }
+        self.swap_attempts = self.swap_attempts.wrapping_add(1);
+        let mut failed = None;
+        for buf in pending {
+            match SwapFile::write(&buf.name, buf.get_rope()) {
+                Ok(()) => buf.unswapped_changes = 0,
+                Err(err) => {
+                    failed = Some(format!("{}: {err}", swap::swap_path(&buf.name).display()))
+                }
+            }
+        }
+        let Some(err) = failed else {
+            self.swap_error_shown = false;
+            return None;
+        };
+        // the timer tries again, the error is shown once
+        if !std::mem::replace(&mut self.swap_error_shown, true) {
+            self.messages.push(
+                UserMessageBuilder::default()
+                    .message(err)
+                    .footer("swap file not written")
+                    .fg(Color::Red),
+            );
+        }
+        None
+    }
+
+    fn has_unswapped_changes(&self) -> bool {
+        self.buffers
+            .iter()
+            .any(|b| !b.is_scratch() && b.unswapped_changes > 0)
+    }
+
+    /// Dumps every modified buffer to a swap file after a panic so `revi -r` can recover it.
+    pub fn emergency_save(&mut self) {
+        let pid = std::process::id();
+        for (i, buf) in self.buffers.iter().enumerate().filter(|(_, b)| b.modified) {
+            let name = if buf.is_scratch() {
+                format!("revi-{pid}-{i}.txt")
+            } else {
+                buf.name.clone()
+            };
+            match SwapFile::write(&name, buf.get_rope()) {
+                Ok(()) => eprintln!("revi: unsaved changes to \"{name}\" written to swap file"),
+                Err(err) => eprintln!("revi: failed to write swap file for \"{name}\": {err}"),
+            }
+        }
+    }
+
+    pub fn quit_editor(&mut self) -> Option<Message> {
+        for buf in self.buffers.iter().filter(|b| !b.is_scratch()) {
+            SwapFile::remove(&buf.name);
+        }
+        // Nobody would see an error on the way out, losing the state is not worth keeping revi open.
+        let _ = self.write_state_file();
+        self.is_running = false;
+        None
+    }
+
+    pub fn command_mode_insert(&mut self, c: impl Into<String>) -> Option<Message> {
+        let size = self.command_size();
+        self.command.paste(&c.into(), false, &size);
+        None
+    }
+
+    /// Room for the command line after the `:`.
+    fn command_size(&self) -> Size {
+        Size::new(self.size.width.saturating_sub(1), 1)
+    }
+
+    /// Moves the command line cursor to char `idx`, as far as just after the last char.
+    fn set_command_cursor(&mut self, idx: usize) {
+        let size = self.command_size();
+        let idx = idx.min(self.command.get_rope().len_chars());
+        self.command.set_cursor_char(idx, &size);
+    }
+
+    /// Replaces the command line with `line` and puts the cursor at the end.
+    fn set_command_line(&mut self, line: &str) {
+        self.command = Buffer::new_str(NO_NAME, line);
+        self.set_command_cursor(usize::MAX);
+    }
+
+    /// `<C-w>` on the command line, deletes the spaces before the cursor and the word before them.
+    pub fn delete_word_before(&mut self) -> Option<Message> {
+        let end = self.command.cursor_char();
+        let chars = self
+            .command
+            .get_rope()
+            .chars()
+            .take(end)
+            .collect::<Vec<_>>();
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let mut start = end;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        if let Some(&last) = chars.get(start.wrapping_sub(1)) {
+            while start > 0
+                && !chars[start - 1].is_whitespace()
+                && is_word(chars[start - 1]) == is_word(last)
+            {
+                start -= 1;
+            }
+        }
+        let size = self.command_size();
+        self.command.remove(start..end, &size);
+        None
+    }
+
+    /// `<C-u>` on the command line, deletes everything before the cursor.
+    pub fn delete_to_line_start(&mut self) -> Option<Message> {
+        let end = self.command.cursor_char();
+        let size = self.command_size();
+        self.command.remove(0..end, &size);
+        None
+    }
+
+    /// Replaces the command line with an older entry of the history that starts with what was typed.
+    pub fn history_up(&mut self) -> Option<Message> {
+        let line = self.command.get_all_text();
+        let entry = self.history.older(&line)?.to_string();
+        self.set_command_line(&entry);
+        None
+    }
+
+    pub fn history_down(&mut self) -> Option<Message> {
+        let entry = self.history.newer()?.to_string();
+        self.set_command_line(&entry);
+        None
+    }
+
+    /// Inserts the register named by `keys` on the command line, `<C-w>` inserts the word under the cursor.
+    fn insert_register(&mut self, keys: Keys) -> Option<Message> {
+        let text = if keys == Keys::new(Key::LW, Modifiers::CTRL) {
+            self.buffers[self.focused].word_at_cursor()
+        } else {
+            keys.as_char().and_then(|name| self.register(name))
+        };
+        let size = self.command_size();
+        self.command.paste(&text?, false, &size);
+        None
+    }
+
+    /// Text of register `name`, `%` is the name of the focused buffer.
+    pub fn register(&self, name: char) -> Option<String> {
+        match name {
+            '%' => Some(self.buffers[self.focused].name.clone()),
+            _ => self.registers.get(name).map(ToString::to_string),
+        }
+    }
+
+    pub fn check_for_mapping(&mut self) -> Option<Message> {
+        let keys = self.key_parse.get_keys();
+        if self.has_longer_mapping(keys) {
+            // Wait for the next key, `timeoutlen` settles ambiguous mappings.
+            return None;
+        }
+        if let Some(mapping) = self.get_mapping(keys) {
+            self.key_parse.clear();
+            self.count = self.pending_count.take();
+            return Some(mapping.into());
+        }
+        self.flush_pending_keys()
+    }
+
+    /// `:let @a = 'text'` puts text in a register, so recorded macros can be edited.
+    fn let_command(&mut self, arg: &str) -> Option<Message> {
+        let parsed = arg.split_once('=').and_then(|(name, value)| {
+            let mut name = name.trim().strip_prefix('@')?.chars();
+            let name = name.next().filter(|_| name.as_str().is_empty())?;
+            let value = value.trim();
+            let text = ['\'', '"']
+                .into_iter()
+                .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))?;
+            Some((name, text))
+        });
+        match parsed {
+            Some((name, text)) if self.registers.set(name, text) => None,
+            _ => Some(
+                UserMessageBuilder::default()
+                    .message(arg)
+                    .footer("usage: let @{register} = 'text'")
+                    .fg(Color::Red)
+                    .build(),
+            ),
+        }
+    }
+
+    /// Keeps the messages of the change being made for `.`.
+    /// Messages returned by other messages follow from them and are left out.
+    fn record_change(&mut self, message: &Message) {
+        let follow_up = std::mem::take(&mut self.follow_up);
+        if self.repeating || follow_up || message.is_input() {
+            return;
+        }
+        if let Some(messages) = &mut self.pending_change {
+            messages.push((message.clone(), self.count));
+        } else if self.mode == Mode::Normal && message.starts_change() {
+            self.pending_change = Some(vec![(message.clone(), self.count)]);
+        }
+    }
+
+    /// The change is done once normal mode is back, after the motion of an operator or
+    /// when insert mode is left.
+    fn finish_change(&mut self) {
+        if self.mode == Mode::Normal && self.pending_change.is_some() {
+            self.last_change = self.pending_change.take();
+        }
+    }
+
+    /// `.` makes the last change again, a count replaces the counts it was made with.
+    pub fn repeat_change(&mut self) -> Option<Message> {
+        let mut messages = self.last_change.clone()?;
+        if let Some(count) = self.count.take() {
+            for (i, (_, c)) in messages.iter_mut().enumerate() {
+                *c = (i == 0).then_some(count);
+            }
+        }
+        self.repeating = true;
+        for (message, count) in messages {
+            self.count = count;
+            self.dispatch(message);
+        }
+        self.repeating = false;
+        None
+    }
+
+    /// `d`, `c`, `y`, `>` and `<` wait for a motion, in visual mode they work on the selection.
+    /// The same operator again works on count lines, another one cancels both.
+    pub fn operator(&mut self, operator: Operator) -> Option<Message> {
+        if self.mode == Mode::Visual {
+            let (start, end) = self.selection()?;
+            let len = self.buffers[self.focused].get_rope().len_chars();
+            self.change_mode(Mode::Normal);
+            let target = Target {
+                range: start..(end + 1).min(len),
+                linewise: false,
+            };
+            return self.apply_operator(operator, target);
+        }
+        if let Some((pending, op_count)) = self.operator {
+            if pending != operator {
+                return self.cancel_motion();
+            }
+            let count = self.take_count().saturating_mul(op_count);
+            self.operator = None;
+            return self.lines_operator(operator, count);
+        }
+        let count = self.take_count();
+        self.change_mode(Mode::OperatorPending);
+        self.operator = Some((operator, count));
+        None
+    }
+
+    /// `S` and `Y`, the operator on count lines like `cc` and `yy`.
+    pub fn line_operator(&mut self, operator: Operator) -> Option<Message> {
+        let count = self.take_count();
+        self.lines_operator(operator, count)
+    }
+
+    /// Runs `operator` on count lines from the cursor line.
+    fn lines_operator(&mut self, operator: Operator, count: usize) -> Option<Message> {
+        let keyword = Keyword::default();
+        let buf = &self.buffers[self.focused];
+        let text = crate::motion::Text::new(buf.get_rope(), &keyword);
+        let (line, _) = buf.line_col(buf.cursor_char());
+        let last = line.saturating_add(count - 1).min(text.last_line());
+        let target = text.lines(line, last);
+        self.finish_operator(operator, Some(target))
+    }
+
+    /// Moves the cursor, or hands the text the motion moves over to the pending operator.
+    /// Counts before the operator and the motion multiply.
+    pub fn motion(&mut self, motion: Motion) -> Option<Message> {
+        let count = self.take_count();
+        let keyword = Keyword::new(&self.options.iskeyword);
+        let buf = &self.buffers[self.focused];
+        let idx = buf.cursor_char();
+        if let Some((operator, op_count)) = self.operator.take() {
+            let change = operator == Operator::Change;
+            let count = count.saturating_mul(op_count);
+            let target = motion.target(buf.get_rope(), idx, count, &keyword, change);
+            return self.finish_operator(operator, target);
+        }
+        let idx = motion.apply(buf.get_rope(), idx, count, &keyword)?;
+        if motion.is_jump() {
+            self.record_jump();
+        }
+        self.go_to_char(self.focused, idx);
+        None
+    }
+
+    /// Text objects select text in visual mode and pick the text for the pending operator.
+    pub fn text_object(&mut self, object: TextObject, inner: bool) -> Option<Message> {
+        let count = self.take_count();
+        let keyword = Keyword::new(&self.options.iskeyword);
+        let buf = &self.buffers[self.focused];
+        let idx = buf.cursor_char();
+        if let Some((operator, op_count)) = self.operator.take() {
+            let count = count.saturating_mul(op_count);
+            let target = object.target(buf.get_rope(), idx, count, inner, &keyword);
+            return self.finish_operator(operator, target);
+        }
+        if self.mode != Mode::Visual {
+            return None;
+        }
+        let target = object.target(buf.get_rope(), idx, count, inner, &keyword)?;
+        if target.range.is_empty() {
+            return None;
+        }
+        self.visual_start = Some(target.range.start);
+        self.go_to_char(self.focused, target.range.end - 1);
+        None
+    }
+
+    /// Back to normal mode to run `operator` on the text a motion or text object picked.
+    fn finish_operator(&mut self, operator: Operator, target: Option<Target>) -> Option<Message> {
+        self.change_mode(Mode::Normal);
+        let Some(target) = target else {
+            // a motion that fails leaves the text alone and is not repeated by `.`
+            self.pending_change = None;
+            return None;
+        };
+        self.apply_operator(operator, target)
+    }
+
+    /// The char typed after `f`, `t`, `F` or `T`, other keys cancel them.
+    fn find_key(&mut self, keys: Keys, forward: bool, till: bool) -> Option<Message> {
+        let Some(c) = keys.as_char().filter(|c| !matches!(c, '\0' | '\u{1b}')) else {
+            return self.cancel_motion();
+        };
+        let find = Find {
+            c,
+            forward,
+            till,
+            again: false,
+        };
+        self.last_find = Some(find);
+        Some(Message::Motion(Motion::Find(find)))
+    }
+
+    /// Drops the count, and the operator waiting for a motion.
+    fn cancel_motion(&mut self) -> Option<Message> {
+        self.count = None;
+        if self.mode == Mode::OperatorPending {
+            self.change_mode(Mode::Normal);
+        }
+        None
+    }
+
+    /// `;` finds the char of the last `f`, `t`, `F` or `T` again, `,` in the other direction.
+    pub fn repeat_find(&mut self, reverse: bool) -> Option<Message> {
+        let Some(find) = self.last_find else {
+            return self.cancel_motion();
+        };
+        let find = if reverse { find.reversed() } else { find };
+        self.motion(Motion::Find(Find {
+            again: true,
+            ..find
+        }))
+    }
+
+    /// Runs `operator` on `target`, the text deleted, changed or yanked goes to the unnamed
+    /// register. `>` and `<` shift every line the target touches.
+    fn apply_operator(&mut self, operator: Operator, target: Target) -> Option<Message> {
+        let Target {
+            mut range,
+            linewise,
+        } = target;
+        let size = self.text_size();
+        let shiftwidth = self.options.shiftwidth as usize;
+        let autoindent = self.options.autoindent;
+        let buf = &mut self.buffers[self.focused];
+        if let Operator::Indent | Operator::Dedent = operator {
+            let (first, _) = buf.line_col(range.start);
+            let (last, _) = buf.line_col(range.end.saturating_sub(1).max(range.start));
+            buf.shift_lines(first, last, shiftwidth, operator == Operator::Dedent);
+            let idx = buf.first_non_blank(first);
+            self.go_to_char(self.focused, idx);
+            return None;
+        }
+        let rope = buf.get_rope();
+        let mut text = rope.slice(range.clone()).to_string();
+        let last_line = linewise && !text.ends_with('\n');
+        if last_line {
+            text.push('\n');
+        }
+        self.registers.set(UNNAMED, &text);
+        match operator {
+            Operator::Yank => {
+                // yanked lines keep the cursor column, on the first of them
+                let idx = match linewise {
+                    true => {
+                        let (_, col) = buf.line_col(buf.cursor_char());
+                        let (line, _) = buf.line_col(range.start);
+                        buf.line_col_to_char(line, col, false)
+                    }
+                    false => range.start,
+                };
+                self.go_to_char(self.focused, idx);
+            }
+            Operator::Delete => {
+                // the last lines of a file without a trailing newline take the one before them
+                if last_line && range.start > 0 {
+                    range.start -= 1;
+                }
+                let start = range.start;
+                buf.remove(range, &size);
+                let idx = match linewise {
+                    true => buf.first_non_blank(buf.line_col(start).0),
+                    false => start,
+                };
+                self.go_to_char(self.focused, idx);
+            }
+            Operator::Change => {
+                // changed lines leave an empty line to type in, indented like the first one
+                if linewise && !last_line {
+                    range.end -= 1;
+                }
+                if linewise && autoindent {
+                    let (line, _) = buf.line_col(range.start);
+                    let blanks = buf.indent(line).chars().count();
+                    range.start = (range.start + blanks).min(range.end);
+                }
+                let start = range.start;
+                buf.remove(range, &size);
+                self.change_mode(Mode::Insert);
+                // past the end of a line that keeps its indent
+                self.buffers[self.focused].set_cursor_char(start, &size);
+                if let Some((insert, ..)) = &mut self.insert {
+                    *insert = start;
+                }
+            }
+            Operator::Indent | Operator::Dedent => unreachable!("shifted above"),
+        }
+        None
+    }
+
+    /// Count typed before the running command, 1 without one.
+    pub fn take_count(&mut self) -> usize {
+        self.count.take().unwrap_or(1)
+    }
+
+    /// `q{reg}` records the keys typed until the next `q` into register `reg`.
+    pub fn start_recording(&mut self, name: char) -> Option<Message> {
+        self.recording = Some((name, Vec::new()));
+        None
+    }
+
+    fn stop_recording(&mut self) -> Option<Message> {
+        let (name, keys) = self.recording.take()?;
+        self.registers.set(name, &keys_to_string(&keys));
+        None
+    }
+
+    /// `@{reg}` types the keys in register `reg` count times, `@@` plays the last one again
+    /// and `@:` runs the last command line.
+    pub fn play_macro(&mut self, name: char) -> Option<Message> {
+        let count = self.take_count();
+        let name = match name {
+            '@' => self.last_macro?,
+            name => name,
+        };
+        let text = self.register(name)?;
+        self.last_macro = Some(name);
+        let text = match name {
+            LAST_COMMAND => format!(":{text}<enter>"),
+            _ => text,
+        };
+        let keys = string_to_keys(&text.repeat(count));
+        Some(Message::FeedKeys(keys, true))
+    }
+
+    fn has_longer_mapping(&self, keys: &[Keys]) -> bool {
+        let local = &self.buffers[self.focused].maps;
+        self.map_keys
+            .has_longer_mapping(&self.mode, keys, local, self.remap)
+    }
+
+    fn get_mapping(&self, keys: &[Keys]) -> Option<Mapping> {
+        let local = &self.buffers[self.focused].maps;
+        self.map_keys
+            .get_mapping(&self.mode, keys, local, self.remap)
+    }
+
+    /// Types the keys of a mapping.
+    pub fn feed_keys(&mut self, keys: &[Keys], remap: bool) -> Option<Message> {
+        if self.map_depth >= MAX_MAP_DEPTH {
+            self.map_aborted = true;
+            self.key_parse.clear();
+            return Some(
+                UserMessageBuilder::default()
+                    .message(keys_to_string(keys))
+                    .footer("recursive mapping")
+                    .fg(Color::Red)
+                    .build(),
+            );
+        }
+        let outer = std::mem::replace(&mut self.remap, remap);
+        self.map_depth += 1;
+        for keys in keys {
+            // A mapping further down hit the limit, stop the whole expansion.
+            if self.map_aborted {
+                break;
+            }
+            self.dispatch(Message::KeyPress(*keys));
+        }
+        self.map_depth -= 1;
+        self.remap = outer;
+        if self.map_depth == 0 {
+            self.map_aborted = false;
+        }
+        None
+    }
+
+    /// The pending keys waited longer than `timeoutlen` for the rest of a mapping.
+    pub fn mapping_timeout(&mut self, generation: usize) -> Option<Message> {
+        if generation != self.key_generation {
+            return None;
+        }
+        self.flush_pending_keys()
+    }
+
+    /// How long to wait for the next key of the pending keys.
+    /// Mappings starting with a special key like `<esc>` use the shorter `ttimeoutlen`.
+    fn mapping_timeout_len(&self) -> Duration {
+        let special = self
+            .key_parse
+            .get_keys()
+            .first()
+            .is_some_and(|k| k.as_char().is_none_or(|c| c == '\0' || c == '\u{1b}'));
+        let millis = if special {
+            self.options.ttimeoutlen
+        } else {
+            self.options.timeoutlen
+        };
+        Duration::from_millis(millis)
+    }
+
+    /// Runs the longest mapping the pending keys start with and replays the keys after it.
+    /// When no mapping matches the first key is typed as text in insert and command mode.
+    fn flush_pending_keys(&mut self) -> Option<Message> {
+        let keys = self.key_parse.get_keys().to_vec();
+        self.key_parse.clear();
+        let first = *keys.first()?;
+        self.count = self.pending_count.take();
+        let longest = (1..=keys.len())
+            .rev()
+            .find_map(|len| self.get_mapping(&keys[..len]).map(|m| (len, m)));
+        let (used, message) = match longest {
+            Some((len, mapping)) => (len, Some(mapping.into())),
+            None => (1, self.unmapped_key(first)),
+        };
+        if let Some(message) = message {
+            self.dispatch(message);
+        }
+        for keys in &keys[used..] {
+            self.dispatch(Message::KeyPress(*keys));
+        }
+        None
+    }
+
+    fn unmapped_key(&self, keys: Keys) -> Option<Message> {
+        let c = keys.as_char().filter(|c| *c != '\0')?;
+        match self.mode {
+            Mode::Command => Some(Message::ModeCommandInsertStr(c.to_string())),
+            Mode::Insert => Some(Message::ModeInsertInsertStr(c.to_string())),
+            Mode::Replace => Some(Message::ModeReplaceStr(c.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Runs `message` and every message that follows from it.
+    pub fn dispatch(&mut self, message: Message) {
+        runtime::update(self, message);
+    }
+
+    pub fn set_option(&mut self, arg: &str) -> Option<Message> {
+        match self.options.set(arg) {
+            Ok(None) => None,
+            Ok(Some(text)) => Some(
+                UserMessageBuilder::default()
+                    .message(text)
+                    .footer("set")
+                    .build(),
+            ),
+            Err(err) => Some(
+                UserMessageBuilder::default()
+                    .message(err)
+                    .footer("set")
+                    .fg(Color::Red)
+                    .build(),
+            ),
+        }
+    }
+
+    /// Replaces `<leader>` and `<localleader>` with the keys they stand for.
+    fn expand_leader(&self, keys: &str) -> String {
+        let mut expanded = String::new();
+        let mut rest = keys;
+        while let Some(start) = rest.find('<') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let lower = rest.to_ascii_lowercase();
+            if lower.starts_with("<leader>") {
+                expanded.push_str(&self.options.mapleader);
+                rest = &rest["<leader>".len()..];
+            } else if lower.starts_with("<localleader>") {
+                expanded.push_str(&self.options.maplocalleader);
+                rest = &rest["<localleader>".len()..];
+            } else {
+                expanded.push('<');
+                rest = &rest[1..];
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Maps `lhs` to the keys `rhs` in `mode`.
+    /// With `local` the mapping only applies to the focused buffer.
+    pub fn add_mapping(
+        &mut self,
+        mode: Mode,
+        lhs: &str,
+        rhs: &str,
+        remap: bool,
+        local: bool,
+        desc: Option<&str>,
+    ) {
+        let lhs = string_to_keys(&self.expand_leader(lhs));
+        let keys = string_to_keys(&self.expand_leader(rhs));
+        let mut binding = Binding::from(Mapping::Keys { keys, remap });
+        if let Some(desc) = desc {
+            binding = binding.with_desc(desc);
+        }
+        let maps = if local {
+            &mut self.buffers[self.focused].maps
+        } else {
+            &mut self.map_keys.user
+        };
+        maps.insert(mode, &lhs, binding);
+    }
+
+    /// Handles `:map`, `:noremap`, `:unmap` and friends.
+    /// `:map lhs rhs` adds a mapping, `:map lhs` and `:map` list them and `:unmap lhs` removes one.
+    /// A leading `<buffer>` works on the mappings of the focused buffer only.
+    pub fn map_command(&mut self, command: MapCommand, args: &str) -> Option<Message> {
+        let args = args.trim_start();
+        let (local, args) = match args.strip_prefix("<buffer>") {
+            Some(args) => (true, args.trim_start()),
+            None => (false, args),
+        };
+        let (lhs, rhs) = args
+            .split_once(char::is_whitespace)
+            .map_or((args, ""), |(lhs, rhs)| (lhs, rhs.trim_start()));
+        let error = |message: &str| {
+            Some(
+                UserMessageBuilder::default()
+                    .message(message)
+                    .footer(lhs)
+                    .fg(Color::Red)
+                    .build(),
+            )
+        };
+        if command.unmap {
+            if lhs.is_empty() {
+                return error("Argument required");
+            }
+            let keys = string_to_keys(&self.expand_leader(lhs));
+            let maps = if local {
+                &mut self.buffers[self.focused].maps
+            } else {
+                &mut self.map_keys.user
+            };
+            if !maps.remove(command.mode, &keys) {
+                return error("No such mapping");
+            }
+            return None;
+        }
+        if rhs.is_empty() {
+            return self.list_mappings(command.mode, lhs, local);
+        }
+        self.add_mapping(command.mode, lhs, rhs, command.remap, local, None);
+        None
+    }
+
+    /// Popup listing the keys that can follow the pending keys, when `whichkey` is on.
+    fn which_key_view(&self) -> Option<Container> {
+        let keys = self.key_parse.get_keys();
+        if !self.options.whichkey || keys.is_empty() {
+            return None;
+        }
+        let local = &self.buffers[self.focused].maps;
+        let mut list = self
+            .map_keys
+            .continuations(&self.mode, keys, local, self.remap);
+        list.sort_by_key(|(key, _)| key.to_string());
+        if list.is_empty() {
+            return None;
+        }
+        let keys = list
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        let key_width = keys.iter().map(|k| k.chars().count()).max().unwrap_or(0);
+        let lines = keys
+            .iter()
+            .zip(list.iter())
+            .map(|(key, (_, desc))| format!(" {key:<key_width$}  {desc} "))
+            .collect::<Vec<_>>();
+        let Size { width, height } = self.size;
+        let text_height = height.saturating_sub(2);
+        let popup_width = lines
+            .iter()
+            .map(|line| line.chars().count() as u16)
+            .max()
+            .unwrap_or(0)
+            .min(width);
+        let popup_height = (lines.len() as u16).min(text_height);
+        let pos = Pos::new(width - popup_width, text_height - popup_height);
+        let rect = Rect::with_position(pos, Size::new(popup_width, popup_height));
+        let popup = lines
+            .iter()
+            .take(popup_height as usize)
+            .map(|line| {
+                Text::new(line)
+                    .max_width(popup_width)
+                    .with_fg(Color::Black)
+                    .with_bg(Color::Grey)
+            })
+            .fold(Container::new(rect, Stack::Vertically), |acc, line| {
+                acc.push(line)
+            });
+        Some(popup)
+    }
+
+    /// Candidates of the command line completion, above the command line where the word starts.
+    fn wildmenu_view(&self) -> Option<Container> {
+        let completion = self.completion.as_ref()?;
+        let line = self.command.get_all_text();
+        if !self.options.wildmenu || self.mode != Mode::Command || completion.line() != line {
+            return None;
+        }
+        let Size { width, height } = self.size;
+        let text_height = height.saturating_sub(2);
+        let popup_height = (completion.items.len() as u16).min(text_height);
+        if popup_height == 0 {
+            return None;
+        }
+        let item_width = completion
+            .items
+            .iter()
+            .map(|item| item.chars().count() as u16 + 2)
+            .max()
+            .unwrap_or(0);
+        let popup_width = item_width.min(width);
+        // One column for the `:` and one for the space the candidates are padded with.
+        let x = (completion.start() as u16).min(width - popup_width);
+        let pos = Pos::new(x, text_height - popup_height);
+        let rect = Rect::with_position(pos, Size::new(popup_width, popup_height));
+        // Scroll the list just enough to keep the selected candidate in view.
+        let selected = completion.selected.unwrap_or(0);
+        let first = (selected + 1).saturating_sub(popup_height as usize);
+        let popup = completion
+            .items
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(popup_height as usize)
+            .map(|(idx, item)| {
+                let bg = if completion.selected == Some(idx) {
+                    Color::White
+                } else {
+                    Color::Grey
+                };
+                Text::new(&format!(" {item} "))
+                    .max_width(popup_width)
+                    .with_fg(Color::Black)
+                    .with_bg(bg)
+            })
+            .fold(Container::new(rect, Stack::Vertically), |acc, line| {
+                acc.push(line)
+            });
+        Some(popup)
+    }
+
+    /// Shows the user's mappings of `mode` that start with `lhs`, buffer local ones are marked with `@`.
+    fn list_mappings(&self, mode: Mode, lhs: &str, local_only: bool) -> Option<Message> {
+        let prefix = string_to_keys(&self.expand_leader(lhs));
+        let local = self.buffers[self.focused].maps.list(&mode, &prefix);
+        let global = if local_only {
+            Vec::new()
+        } else {
+            self.map_keys.user.list(&mode, &prefix)
+        };
+        let mode_char = match mode {
+            Mode::Normal => 'n',
+            Mode::Insert | Mode::Replace => 'i',
+            Mode::Command => 'c',
+            Mode::Visual => 'v',
+            Mode::OperatorPending => 'o',
+        };
+        let lines = local
+            .iter()
+            .map(|m| (m, '@'))
+            .chain(global.iter().map(|m| (m, ' ')))
+            .map(|((keys, mapping), marker)| {
+                format!("{mode_char}  {:<10}{marker}{mapping}", keys_to_string(keys))
+            })
+            .collect::<Vec<_>>();
+        let message = if lines.is_empty() {
+            "No mapping found".to_string()
+        } else {
+            lines.join("\n")
+        };
+        Some(
+            UserMessageBuilder::default()
+                .message(message)
+                .footer("map")
+                .build(),
+        )
+    }
+}
+
+impl App for State {
+    type Settings = Settings;
+    type Message = Message;
+
+    fn new(settings: Self::Settings) -> Self {
+        let buffers = if settings.buffers.is_empty() {
+            vec![Buffer::default()]
+        } else {
+            settings.buffers
+        };
+        let names = buffers
+            .iter()
+            .filter(|b| !b.is_scratch())
+            .map(|b| b.name.clone())
+            .collect::<Vec<_>>();
+        let mut state = Self {
+            focused: 0,
+            buffers,
+            messages: Vec::new(),
+            prompts: Vec::new(),
+            command: Buffer::default(),
+            command_list: trie::Trie::from(
+                &vec![
+                    "b",
+                    "buffer",
+                    "checktime",
+                    "ls",
+                    "e",
+                    "edit",
+                    "jumps",
+                    "let",
+                    "marks",
+                    "mks",
+                    "mksession",
+                    "q",
+                    "quit",
+                    "set",
+                    "stop",
+                    "suspend",
+                    "w",
+                    "write",
+                ]
+                .into_iter()
+                .chain(MapCommand::NAMES.iter().copied())
+                .collect::<Vec<_>>(),
+            ),
+            completion: None,
+            history: History::default(),
+            registers: Registers::default(),
+            pending_register: false,
+            recording: None,
+            last_macro: None,
+            pending_count: None,
+            count: None,
+            insert: None,
+            pending_find: None,
+            last_find: None,
+            pending_replace: false,
+            replaced: Vec::new(),
+            operator: None,
+            last_change: None,
+            pending_change: None,
+            repeating: false,
+            follow_up: false,
+            state_file: settings.state_file,
+            positions: BTreeMap::new(),
+            file_marks: BTreeMap::new(),
+            jump_seq: 0,
+            swap_attempts: 0,
+            swap_error_shown: false,
+            jump_idx: None,
+            session_data: None,
+            map_keys: Mapper::default(),
+            key_parse: KeyParser::default(),
+            key_generation: 0,
+            remap: true,
+            map_depth: 0,
+            map_aborted: false,
+            options: Options::default(),
+            visual_start: None,
+            mode: Mode::Normal,
+            size: size(),
+            is_running: true,
+        };
+        state.load_state_file();
+        for idx in 0..state.buffers.len() {
+            state.restore_position(idx);
+            state.restore_file_marks(idx);
+        }
+        if let Some(path) = &settings.session {
+            if let Err(err) = state.load_session(path) {
+                state.messages.push(
+                    UserMessageBuilder::default()
+                        .message(err)
+                        .footer("Session")
+                        .fg(Color::Red),
+                );
+            }
+        }
+        for name in names.iter().rev() {
+            if settings.recover {
+                if let Some(Message::UserMessage(builder)) = state.recover_swap(name) {
+                    state.messages.push(builder);
+                }
+            } else {
+                state.check_swap_file(name);
+            }
+        }
+        state
+    }
+
+    fn view(&self) -> revi_ui::widget::BoxWidget {
+        let Size { width, height } = self.size;
+        let rect = Rect::new(self.size);
+
+        if let Some(prompt) = self.prompts.last() {
+            return prompt.as_view(rect).into();
+        }
+
+        if let Some(builder) = self.messages.last() {
+            return builder.as_view(rect).into();
+        }
+
+        let buf = &self.buffers[self.focused];
+        // ------ TEXT AREA --------
+        let text_size = Size {
+            width,
+            height: height - 2,
+        };
+        let rect_text = Rect::new(text_size);
+        // let mut p = tree_sitter::Parser::new();
+        // p.set_language(tree_sitter_md::language())
+        //     .expect("Error loading Rust grammar");
+
+        // let mut last_context_parser: Option<tree_sitter::Tree> = None;
+        let text = buf
+            .on_screen(&text_size)
+            .iter()
+            .enumerate()
+            .map(|(row, line)| {
+                self.text_line(line, row, width)
+                // last_context_parser = p.parse(line, last_context_parser.as_ref());
+                // let mut text_line =
+                //     Container::new(Rect::new(Size::new(width, 1)), Stack::Horizontally);
+                // if let Some(tree) = &last_context_parser {
+                //     let mut cursor = tree.walk();
+                //     let mut node = cursor.node();
+                //     for n in node.children(&mut cursor) {
+                //         let t = Text::new(n.kind()).max_width(width);
+                //         text_line = text_line.push(t);
+                //     }
+                // } else {
+                //     let t = Text::new(line.as_str()).max_width(width);
+                //     text_line = text_line.push(t);
+                // }
+                // text_line
+            })
+            .chain(std::iter::repeat(Text::new(" ").max_width(width).into()))
+            // .chain(std::iter::repeat(
+            //     Container::new(Rect::new(Size::new(width, 1)), Stack::Horizontally)
+            //         .push(Text::new(" ").max_width(width)),
+            // ))
+            .take(height as usize)
+            .fold(Container::new(rect_text, Stack::Vertically), |acc, item| {
+                acc.push(item)
             });
 
         // ------ CMD AREA --------
@@ -437,6 +2336,10 @@ impl App for State {
             .iter()
             .map(ToString::to_string)
             .collect::<String>();
+        let src_cmd = match (&self.recording, self.mode) {
+            (Some((name, _)), mode) if mode != Mode::Command => format!("recording @{name}"),
+            _ => src_cmd,
+        };
         let visable_colon = match self.mode {
             Mode::Command => ":",
             _ => " ",
@@ -460,7 +2363,7 @@ impl App for State {
 
         let cursor = buf.get_cursor();
         let cursor_pos_status_width =
-            width - (mode_status.char_len() + filename_status.char_len()) as u16;
+            width.saturating_sub((mode_status.char_len() + filename_status.char_len()) as u16);
         let pos = cursor.pos();
         let col = pos.x;
         let row = pos.y;
@@ -487,14 +2390,31 @@ impl App for State {
             .push(cmd);
 
         // ------ All widgets AREA --------
-        Container::new(rect, Stack::Vertically)
+        let editor = Container::new(rect, Stack::Vertically)
             .push(text)
-            .push(info)
+            .push(info);
+
+        // ------ Which Key and Wildmenu Popup AREA --------
+        let popups = [self.which_key_view(), self.wildmenu_view()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        if popups.is_empty() {
+            return editor.into();
+        }
+        popups
+            .into_iter()
+            .fold(
+                Container::new(rect, Stack::Layered).push(editor),
+                |acc, popup| acc.push(popup),
+            )
             .into()
     }
 
     fn update(&mut self, message: Self::Message) -> Option<Self::Message> {
-        match message {
+        let input = message.is_input();
+        self.record_change(&message);
+        let next = match message {
             Message::CursorUp => self.cursor_up(),
             Message::CursorDown => self.cursor_down(),
             Message::CursorLeft => self.cursor_left(),
@@ -513,23 +2433,86 @@ impl App for State {
             Message::CheckForMapping => self.check_for_mapping(),
             Message::ModeCommandInsertStr(s) => self.command_mode_insert(s),
             Message::ModeInsertInsertStr(s) => self.insert_mode_insert(s),
+            Message::ModeReplaceStr(s) => self.replace_mode_insert(&s),
             Message::ChangeMode(mode) => self.change_mode(mode),
             Message::ExecuteCommand => self.execute_command(),
             Message::BufferList => self.buffer_list_command(),
             Message::EditFile(ref filename) => self.edit_file_command(filename),
             Message::SwapBuffer(ref arg) => self.swap_buffer_command(arg),
             Message::CloseCurrentPaneOnKeyPress => self.close_message(),
-            Message::NextAvailableCommand => self.next_available_command(),
+            Message::NextAvailableCommand => self.next_available_command(true),
+            Message::PrevAvailableCommand => self.next_available_command(false),
+            Message::DeleteWordBefore => self.delete_word_before(),
+            Message::DeleteToLineStart => self.delete_to_line_start(),
+            Message::HistoryUp => self.history_up(),
+            Message::HistoryDown => self.history_down(),
+            Message::InsertRegister => {
+                self.pending_register = true;
+                None
+            }
             Message::Resize(size) => {
                 self.size = size;
                 None
             }
             Message::Save(filename) => self.save(filename),
-            Message::Quit => {
-                self.is_running = false;
+            Message::PromptKey(keys) => self.prompt_key(keys),
+            Message::RecoverSwap(ref name) => self.recover_swap(name),
+            Message::DeleteSwap(ref name) => self.delete_swap(name),
+            Message::WriteSwapFiles => self.write_swap_files(0),
+            Message::MappingTimeout(generation) => self.mapping_timeout(generation),
+            Message::SetOption(ref arg) => self.set_option(arg),
+            Message::FeedKeys(ref keys, remap) => self.feed_keys(keys, remap),
+            Message::Map(command, ref args) => self.map_command(command, args),
+            Message::Paste(ref text) => self.paste(text),
+            Message::Mouse(event) => self.mouse(event),
+            Message::Suspend => {
+                runtime::suspend();
+                None
+            }
+            Message::CheckTime => self.check_time(),
+            Message::FilesChanged(ref names) => self.check_files(names, true),
+            Message::ReloadBuffer(ref name) => self.reload_buffer(name),
+            Message::CursorTopOfBuffer => self.jump_to_line(0),
+            Message::CursorBottomOfBuffer => self.jump_to_line(usize::MAX),
+            Message::RepeatChange => self.repeat_change(),
+            Message::RecordMacro(name) => self.start_recording(name),
+            Message::PlayMacro(name) => self.play_macro(name),
+            Message::SetMark(name) => self.set_mark(name),
+            Message::GoToMark(name, exact) => self.go_to_mark(name, exact),
+            Message::JumpOlder => self.jump_older(),
+            Message::JumpNewer => self.jump_newer(),
+            Message::ChangeOlder => self.change_older(),
+            Message::ChangeNewer => self.change_newer(),
+            Message::ListMarks => self.list_marks(),
+            Message::ListJumps => self.list_jumps(),
+            Message::Operator(operator) => self.operator(operator),
+            Message::Motion(motion) => self.motion(motion),
+            Message::FindChar(forward, till) => {
+                self.pending_find = Some((forward, till));
                 None
             }
+            Message::RepeatFind(reverse) => self.repeat_find(reverse),
+            Message::ReplaceChar => {
+                self.pending_replace = true;
+                None
+            }
+            Message::Replace(c) => self.replace(c),
+            Message::TextObject(object, inner) => self.text_object(object, inner),
+            Message::LineOperator(operator) => self.line_operator(operator),
+            Message::OpenLine(below) => self.open_line(below),
+            Message::JoinLines => self.join_lines(),
+            Message::Put(after) => self.put(after),
+            Message::MakeSession(ref name, force, ref data) => {
+                self.make_session(name, force, data.clone())
+            }
+            Message::Quit => self.quit_editor(),
+        };
+        if self.is_running {
+            self.write_swap_files(SWAP_CHANGES);
         }
+        self.finish_change();
+        self.follow_up = next.is_some() && !input;
+        next
     }
 
     fn cursor_pos(&self) -> Option<Pos> {
@@ -537,7 +2520,8 @@ impl App for State {
             Mode::Command => {
                 let cursor = self.command.get_cursor();
                 let x = cursor.pos.x + 1;
-                let y = cursor.pos.y + self.size.height;
+                // the command line is the last row
+                let y = cursor.pos.y + self.size.height.saturating_sub(1);
                 Some(Pos { x, y })
             }
             _ => {
@@ -552,19 +2536,77 @@ impl App for State {
             Mode::Normal => Some(SetCursorStyle::BlinkingBlock),
             Mode::Command => Some(SetCursorStyle::BlinkingBar),
             Mode::Insert => Some(SetCursorStyle::BlinkingBar),
+            Mode::Visual => Some(SetCursorStyle::BlinkingBlock),
+            Mode::OperatorPending => Some(SetCursorStyle::BlinkingUnderScore),
+            Mode::Replace => Some(SetCursorStyle::SteadyUnderScore),
         }
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
         let close_message = !self.messages.is_empty();
-        Subscription::none().push(move |event| match event {
+        let answer_prompt = !self.prompts.is_empty();
+        let unswapped_changes = self.has_unswapped_changes();
+        let mut subscription = Subscription::none();
+        if unswapped_changes {
+            let id = format!("swap-{}", self.swap_attempts);
+            subscription = subscription.after(id, SWAP_INTERVAL, Message::WriteSwapFiles);
+        }
+        if self.options.watchfiles {
+            let paths = self
+                .buffers
+                .iter()
+                .filter(|b| !b.is_scratch())
+                .map(|b| b.name.clone())
+                .collect::<Vec<_>>();
+            if !paths.is_empty() {
+                let id = watch::id(&paths);
+                subscription = subscription.run(id, move |sender| watch::poll(paths, sender));
+            }
+        }
+        if self.options.timeout && !self.key_parse.get_keys().is_empty() {
+            let generation = self.key_generation;
+            subscription = subscription.after(
+                format!("timeoutlen-{generation}"),
+                self.mapping_timeout_len(),
+                Message::MappingTimeout(generation),
+            );
+        }
+        subscription.push(move |event| match event {
             Event::Key(k) => {
+                let keys = Keys::from_event(k)?;
+                if answer_prompt {
+                    return Some(Message::PromptKey(keys));
+                }
+                if close_message {
+                    return Some(Message::CloseCurrentPaneOnKeyPress);
+                }
+                Some(Message::KeyPress(keys))
+            }
+            Event::Paste(text) => {
+                if answer_prompt {
+                    return None;
+                }
                 if close_message {
                     return Some(Message::CloseCurrentPaneOnKeyPress);
                 }
-                Some(Message::KeyPress(Keys::from(k)))
+                Some(Message::Paste(text))
+            }
+            Event::Mouse(event) => {
+                if answer_prompt {
+                    return None;
+                }
+                if close_message {
+                    return match event.kind {
+                        MouseEventKind::Down(_) => Some(Message::CloseCurrentPaneOnKeyPress),
+                        _ => None,
+                    };
+                }
+                Some(Message::Mouse(event))
             }
             Event::Resize(w, h) => Some(Message::Resize(Size::new(w, h))),
+            Event::FocusGained => Some(Message::CheckTime),
+            // Get unsaved edits into swap files while the user is away.
+            Event::FocusLost if unswapped_changes => Some(Message::WriteSwapFiles),
             _ => None,
         })
     }
@@ -572,4 +2614,8 @@ impl App for State {
     fn quit(&self) -> bool {
         self.is_running
     }
+
+    fn mouse_capture(&self) -> bool {
+        self.options.mouse
+    }
 }
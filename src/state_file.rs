@@ -0,0 +1,389 @@
+//! Command history, registers, global marks and the last cursor position in each file survive
+//! between sessions in the state file, like vim's viminfo. Every revi merges what it has into the
+//! file when it quits, so several instances can share it.
+use crate::history::Entry;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &str = "revi-state 1";
+/// Files whose last cursor position is remembered.
+const MAX_POSITIONS: usize = 100;
+/// A lock older than this was left behind by a revi that died while writing.
+const STALE_LOCK: Duration = Duration::from_secs(10);
+
+/// `$XDG_STATE_HOME/revi/state`, falling back to `~/.local/state/revi/state`.
+pub fn default_path() -> PathBuf {
+    crate::paths::state_dir().join("state")
+}
+
+/// Seconds since the epoch, the newer entry wins when state files are merged.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Where the cursor was when a file was last left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+    pub time: u64,
+}
+
+/// A global mark `A-Z`, the file it is in and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMark {
+    /// Absolute path of the file.
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateFile {
+    /// Command lines, oldest first.
+    pub history: Vec<Entry>,
+    pub registers: BTreeMap<char, (String, u64)>,
+    /// Cursor positions by absolute path.
+    pub positions: BTreeMap<String, Position>,
+    pub marks: BTreeMap<char, FileMark>,
+}
+
+impl StateFile {
+    /// Reads the state file at `path`, a missing or unreadable file is an empty state.
+    pub fn read(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .map(|src| Self::parse(&src))
+            .unwrap_or_default()
+    }
+
+    /// Lines that do not parse are skipped, a file from another version is ignored.
+    fn parse(src: &str) -> Self {
+        let mut state = Self::default();
+        let mut lines = src.lines();
+        if lines.next() != Some(MAGIC) {
+            return state;
+        }
+        for line in lines {
+            let Some((kind, rest)) = line.split_once(' ') else {
+                continue;
+            };
+            let _ = match kind {
+                "history" => state.parse_history(rest),
+                "register" => state.parse_register(rest),
+                "position" => state.parse_position(rest),
+                "mark" => state.parse_mark(rest),
+                _ => None,
+            };
+        }
+        state
+    }
+
+    fn parse_history(&mut self, rest: &str) -> Option<()> {
+        let (time, line) = rest.split_once(' ')?;
+        self.history.push(Entry {
+            line: unescape(line),
+            time: time.parse().ok()?,
+        });
+        Some(())
+    }
+
+    fn parse_register(&mut self, rest: &str) -> Option<()> {
+        let mut chars = rest.chars();
+        let name = chars.next()?;
+        let (time, text) = chars.as_str().trim_start().split_once(' ')?;
+        self.registers
+            .insert(name, (unescape(text), time.parse().ok()?));
+        Some(())
+    }
+
+    fn parse_position(&mut self, rest: &str) -> Option<()> {
+        let mut parts = rest.splitn(4, ' ');
+        let time = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let col = parts.next()?.parse().ok()?;
+        let path = parts.next()?.to_string();
+        self.positions.insert(path, Position { line, col, time });
+        Some(())
+    }
+
+    fn parse_mark(&mut self, rest: &str) -> Option<()> {
+        let mut parts = rest.splitn(5, ' ');
+        let name = parts.next()?.chars().next()?;
+        let time = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let col = parts.next()?.parse().ok()?;
+        let path = parts.next()?.to_string();
+        let mark = FileMark {
+            path,
+            line,
+            col,
+            time,
+        };
+        self.marks.insert(name, mark);
+        Some(())
+    }
+
+    /// Merges `other` into `self`, the newer of two entries for the same thing wins.
+    /// Keeps the `max_history` newest command lines. Times are whole seconds, so lines run in
+    /// the same second keep the order they were in, ours before `other`'s.
+    pub fn merge(&mut self, other: Self, max_history: usize) {
+        let mut newest = BTreeMap::new();
+        for (idx, entry) in self.history.drain(..).chain(other.history).enumerate() {
+            let order = newest.entry(entry.line).or_insert((entry.time, idx));
+            *order = (entry.time, idx).max(*order);
+        }
+        let mut history = newest
+            .into_iter()
+            .map(|(line, order)| (order, line))
+            .collect::<Vec<_>>();
+        history.sort_unstable();
+        let mut history = history
+            .into_iter()
+            .map(|((time, _), line)| Entry { line, time })
+            .collect::<Vec<_>>();
+        let extra = history.len().saturating_sub(max_history);
+        self.history = history.split_off(extra);
+        for (name, (text, time)) in other.registers {
+            if self.registers.get(&name).is_none_or(|(_, t)| time >= *t) {
+                self.registers.insert(name, (text, time));
+            }
+        }
+        for (path, position) in other.positions {
+            if self
+                .positions
+                .get(&path)
+                .is_none_or(|p| position.time >= p.time)
+            {
+                self.positions.insert(path, position);
+            }
+        }
+        for (name, mark) in other.marks {
+            if self.marks.get(&name).is_none_or(|m| mark.time >= m.time) {
+                self.marks.insert(name, mark);
+            }
+        }
+        if self.positions.len() > MAX_POSITIONS {
+            let mut times = self.positions.values().map(|p| p.time).collect::<Vec<_>>();
+            times.sort_unstable();
+            let oldest_kept = times[times.len() - MAX_POSITIONS];
+            self.positions.retain(|_, p| p.time >= oldest_kept);
+        }
+    }
+
+    /// Merges `self` into the state file at `path`.
+    /// The file is locked while merging and replaced in one rename, so instances that quit
+    /// at the same time keep each other's state and readers never see half a file.
+    pub fn write(self, path: &Path, max_history: usize) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let _lock = Lock::acquire(path)?;
+        let mut state = Self::read(path);
+        state.merge(self, max_history);
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&tmp)?;
+            write!(file, "{state}")?;
+            file.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+}
+
+impl std::fmt::Display for StateFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{MAGIC}")?;
+        for entry in &self.history {
+            writeln!(f, "history {} {}", entry.time, escape(&entry.line))?;
+        }
+        for (name, (text, time)) in &self.registers {
+            writeln!(f, "register {name} {time} {}", escape(text))?;
+        }
+        for (path, p) in &self.positions {
+            writeln!(f, "position {} {} {} {path}", p.time, p.line, p.col)?;
+        }
+        for (name, m) in &self.marks {
+            writeln!(f, "mark {name} {} {} {} {}", m.time, m.line, m.col, m.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps every entry on one line.
+pub fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+pub fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Held while a revi merges its state into the file.
+struct Lock(PathBuf);
+
+impl Lock {
+    fn acquire(path: &Path) -> io::Result<Self> {
+        let lock = path.with_extension("lock");
+        for _ in 0..100 {
+            match OpenOptions::new().write(true).create_new(true).open(&lock) {
+                Ok(_) => return Ok(Self(lock)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(&lock)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|time| time.elapsed().ok());
+                    if age.is_some_and(|age| age > STALE_LOCK) {
+                        let _ = fs::remove_file(&lock);
+                        continue;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::Error::other(format!(
+            "{} is locked by another revi",
+            path.display()
+        )))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+fn entry(line: &str, time: u64) -> Entry {
+    Entry {
+        line: line.into(),
+        time,
+    }
+}
+
+#[test]
+fn state_file_round_trip() {
+    let mut state = StateFile::default();
+    state.history.push(entry("set mouse", 1));
+    state.history.push(entry("e C:\\dir\\file", 2));
+    state.registers.insert('a', ("two\nlines".into(), 3));
+    state.positions.insert(
+        "/tmp/some file.rs".into(),
+        Position {
+            line: 4,
+            col: 2,
+            time: 5,
+        },
+    );
+    state.marks.insert(
+        'A',
+        FileMark {
+            path: "/tmp/some file.rs".into(),
+            line: 1,
+            col: 0,
+            time: 6,
+        },
+    );
+    assert_eq!(StateFile::parse(&state.to_string()), state);
+    assert_eq!(
+        StateFile::parse("something else\nhistory 1 w"),
+        StateFile::default()
+    );
+}
+
+#[test]
+fn merge_keeps_newest_entries() {
+    let mut ours = StateFile {
+        history: vec![entry("w", 5), entry("ls", 6)],
+        ..StateFile::default()
+    };
+    ours.registers.insert('a', ("ours".into(), 10));
+    let mut theirs = StateFile {
+        history: vec![entry("q", 1), entry("ls", 2), entry("set", 7)],
+        ..StateFile::default()
+    };
+    theirs.registers.insert('a', ("theirs".into(), 9));
+    theirs.registers.insert('b', ("b".into(), 1));
+    let mark = |path: &str, time| FileMark {
+        path: path.into(),
+        line: 0,
+        col: 0,
+        time,
+    };
+    ours.marks.insert('A', mark("/ours", 4));
+    theirs.marks.insert('A', mark("/theirs", 8));
+    theirs.marks.insert('B', mark("/b", 1));
+    ours.merge(theirs, 3);
+    assert_eq!(
+        ours.history,
+        vec![entry("w", 5), entry("ls", 6), entry("set", 7)]
+    );
+    assert_eq!(ours.registers[&'a'].0, "ours");
+    assert_eq!(ours.registers[&'b'].0, "b");
+    assert_eq!(ours.marks[&'A'].path, "/theirs");
+    assert_eq!(ours.marks[&'B'].path, "/b");
+}
+
+#[test]
+fn merge_keeps_the_order_of_lines_run_in_the_same_second() {
+    let mut ours = StateFile {
+        history: vec![entry("w", 5), entry("e b", 5), entry("bn", 5)],
+        ..StateFile::default()
+    };
+    let theirs = StateFile {
+        history: vec![entry("set", 5), entry("e b", 4), entry("ls", 6)],
+        ..StateFile::default()
+    };
+    ours.merge(theirs, 10);
+    assert_eq!(
+        ours.history,
+        vec![
+            entry("w", 5),
+            entry("e b", 5),
+            entry("bn", 5),
+            entry("set", 5),
+            entry("ls", 6)
+        ]
+    );
+}
+
+#[test]
+fn write_merges_with_file_on_disk() {
+    let dir = std::env::temp_dir().join(format!("revi-state-{}", std::process::id()));
+    let path = dir.join("state");
+    let _ = fs::remove_file(&path);
+    let mut first = StateFile::default();
+    first.history.push(entry("first", 1));
+    first.write(&path, 10).unwrap();
+    let mut second = StateFile::default();
+    second.history.push(entry("second", 2));
+    second.write(&path, 10).unwrap();
+    let state = StateFile::read(&path);
+    assert_eq!(state.history, vec![entry("first", 1), entry("second", 2)]);
+    assert!(!path.with_extension("lock").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
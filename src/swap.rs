@@ -0,0 +1,175 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::paths::absolute;
+use ropey::Rope;
+
+const MAGIC: &str = "revi-swap 1";
+
+/// Directory swap files are written to.
+/// `$XDG_STATE_HOME/revi/swap`, falling back to `~/.local/state/revi/swap`.
+pub fn swap_dir() -> PathBuf {
+    crate::paths::state_dir().join("swap")
+}
+
+/// Location of the swap file for the buffer named `name`.
+/// The absolute path of the file is flattened with `%` like vim does for `directory//`.
+pub fn swap_path(name: &str) -> PathBuf {
+    let flat = absolute(name).to_string_lossy().replace(['/', '\\'], "%");
+    swap_dir().join(format!("{flat}.swp"))
+}
+
+#[cfg(target_os = "linux")]
+fn process_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
+
+#[derive(Debug, Clone)]
+pub struct SwapFile {
+    pub path: PathBuf,
+    pub file: String,
+    pub pid: u32,
+}
+
+impl SwapFile {
+    /// Writes `rope` to the swap file of `name`.
+    /// The file is written next to its final location and renamed over it
+    /// so a crash mid write never leaves a truncated swap behind.
+    pub fn write(name: &str, rope: &Rope) -> std::io::Result<()> {
+        let path = swap_path(name);
+        fs::create_dir_all(swap_dir())?;
+        let tmp = path.with_extension("swp.tmp");
+        {
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&tmp)?;
+            let mut writer = BufWriter::new(file);
+            writeln!(writer, "{MAGIC}")?;
+            writeln!(writer, "pid {}", std::process::id())?;
+            writeln!(writer, "file {}", absolute(name).display())?;
+            rope.write_to(&mut writer)?;
+            writer.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    /// Reads only the header of a swap file.
+    pub fn open(path: &Path) -> Option<Self> {
+        let mut reader = BufReader::new(File::open(path).ok()?);
+        Self::read_header(path, &mut reader)
+    }
+
+    fn read_header(path: &Path, reader: &mut impl BufRead) -> Option<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        if line.trim_end() != MAGIC {
+            return None;
+        }
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let pid = line.trim_end().strip_prefix("pid ")?.parse().ok()?;
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let file = line.trim_end().strip_prefix("file ")?.to_string();
+        Some(Self {
+            path: path.to_path_buf(),
+            file,
+            pid,
+        })
+    }
+
+    /// Finds the swap file left behind for the buffer named `name`.
+    pub fn find(name: &str) -> Option<Self> {
+        Self::open(&swap_path(name))
+    }
+
+    /// Lists every swap file in the swap directory.
+    pub fn list() -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(swap_dir()) else {
+            return vec![];
+        };
+        let mut list = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "swp"))
+            .filter_map(|path| Self::open(&path))
+            .collect::<Vec<_>>();
+        list.sort_by(|a, b| a.file.cmp(&b.file));
+        list
+    }
+
+    /// Removes the swap file of the buffer named `name` if there is one.
+    pub fn remove(name: &str) {
+        let _ = fs::remove_file(swap_path(name));
+    }
+
+    /// Recovered text of the buffer.
+    pub fn contents(&self) -> std::io::Result<String> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let Some(_) = Self::read_header(&self.path, &mut reader) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is not a revi swap file", self.path.display()),
+            ));
+        };
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    /// True when this revi wrote the swap file.
+    pub fn is_ours(&self) -> bool {
+        self.pid == std::process::id()
+    }
+
+    /// True when the revi that wrote the swap file is still running.
+    pub fn is_running(&self) -> bool {
+        process_running(self.pid)
+    }
+}
+
+/// Prints every recoverable swap file, used by `revi -r`.
+pub fn print_recoverable() {
+    let list = SwapFile::list();
+    println!("Swap files found in {}:", swap_dir().display());
+    if list.is_empty() {
+        println!("   -- none --");
+        return;
+    }
+    for (i, swap) in list.iter().enumerate() {
+        let status = if swap.is_running() {
+            "STILL RUNNING"
+        } else {
+            "not running"
+        };
+        println!("{:>4}. {}", i + 1, swap.file);
+        println!("          process ID: {} ({status})", swap.pid);
+    }
+}
+
+#[test]
+fn swap_path_flattens_directories() {
+    let path = swap_path("/tmp/revi/test.rs");
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    assert_eq!(name, "%tmp%revi%test.rs.swp");
+}
+
+#[test]
+fn swap_header_round_trip() {
+    let src = format!("{MAGIC}\npid 42\nfile /tmp/foo.rs\nhello\nworld\n");
+    let mut reader = BufReader::new(src.as_bytes());
+    let swap = SwapFile::read_header(Path::new("foo.swp"), &mut reader).unwrap();
+    assert_eq!(swap.pid, 42);
+    assert_eq!(swap.file, "/tmp/foo.rs");
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "hello\nworld\n");
+}
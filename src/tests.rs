@@ -0,0 +1,1038 @@
+//! End to end tests that drive `State` with key presses and check what ends up on screen.
+use crate::buffer::{Buffer, NO_NAME};
+use crate::map_keys::{MapCommand, Mapper};
+use crate::message::Message;
+use crate::state::State;
+use crate::{Mode, Settings};
+use revi_ui::application::App;
+use revi_ui::backend::TestBackend;
+use revi_ui::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use revi_ui::grid::Grid;
+use revi_ui::layout::{Pos, Size};
+use revi_ui::{runtime, string_to_keys, Color, Key, Keys, SetCursorStyle};
+
+const SIZE: Size = Size {
+    width: 20,
+    height: 6,
+};
+
+/// Editor with a single scratch buffer, scratch buffers never write swap files.
+pub fn editor(src: &str) -> State {
+    let settings = Settings {
+        buffers: vec![Buffer::new_str(NO_NAME, src)],
+        recover: false,
+        state_file: None,
+        session: None,
+    };
+    let mut state = State::new(settings);
+    state.size = SIZE;
+    state
+}
+
+pub fn feed(state: &mut State, keys: &str) {
+    for keys in string_to_keys(keys) {
+        runtime::update(state, Message::KeyPress(keys));
+    }
+}
+
+/// Lets the `timeoutlen` timer of the pending keys fire.
+pub fn timeout(state: &mut State) {
+    let generation = state.key_generation;
+    runtime::update(state, Message::MappingTimeout(generation));
+}
+
+pub fn screen(state: &State) -> TestBackend {
+    let mut backend = TestBackend::new(state.size);
+    runtime::render(&mut backend, state, &mut Grid::default()).expect("render to memory");
+    backend
+}
+
+/// Rows of the text area with trailing spaces removed.
+pub fn text_area(state: &State) -> Vec<String> {
+    let lines = screen(state).lines();
+    lines[..lines.len() - 2]
+        .iter()
+        .map(|line| line.trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn renders_buffer_text() {
+    let state = editor("hello\nworld\n");
+    assert_eq!(text_area(&state), vec!["hello", "world", "", ""]);
+}
+
+#[test]
+fn insert_mode_typing_shows_up_on_screen() {
+    let mut state = editor("world\n");
+    feed(&mut state, "ihello <esc>");
+    assert_eq!(text_area(&state)[0], "hello world");
+    let backend = screen(&state);
+    assert!(matches!(
+        backend.cursor_shape(),
+        Some(SetCursorStyle::BlinkingBlock)
+    ));
+}
+
+#[test]
+fn status_bar_shows_mode_with_style() {
+    let mut state = editor("abc\n");
+    feed(&mut state, "i");
+    let backend = screen(&state);
+    let status = &backend.lines()[SIZE.height as usize - 2];
+    assert!(status.starts_with("Insert"));
+    let cell = backend.screen().get(0, SIZE.height - 2).unwrap();
+    assert_eq!(cell.style.background_color, Some(Color::White));
+    assert!(matches!(
+        backend.cursor_shape(),
+        Some(SetCursorStyle::BlinkingBar)
+    ));
+}
+
+#[test]
+fn command_line_echoes_typed_command() {
+    let mut state = editor("abc\n");
+    feed(&mut state, ":ls");
+    let lines = screen(&state).lines();
+    assert_eq!(lines[SIZE.height as usize - 1].trim_end(), ":ls");
+}
+
+#[test]
+fn cursor_moves_with_hjkl() {
+    let mut state = editor("abc\ndef\nghi\n");
+    feed(&mut state, "jjl");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 2));
+    feed(&mut state, "kh");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 1));
+}
+
+#[test]
+fn ambiguous_mapping_waits_for_timeout() {
+    let mut state = editor("\n");
+    state.map_keys =
+        Mapper::default().with_mapping(Mode::Insert, "jk", Message::ChangeMode(Mode::Normal));
+    feed(&mut state, "ij");
+    assert_eq!(text_area(&state)[0], "");
+    timeout(&mut state);
+    assert_eq!(text_area(&state)[0], "j");
+    feed(&mut state, "jk");
+    assert!(matches!(state.mode, Mode::Normal));
+}
+
+#[test]
+fn stale_mapping_timeout_is_ignored() {
+    let mut state = editor("\n");
+    state.map_keys =
+        Mapper::default().with_mapping(Mode::Insert, "jk", Message::ChangeMode(Mode::Normal));
+    feed(&mut state, "ij");
+    let stale = state.key_generation;
+    feed(&mut state, "k");
+    runtime::update(&mut state, Message::MappingTimeout(stale));
+    assert!(matches!(state.mode, Mode::Normal));
+    assert_eq!(text_area(&state)[0], "");
+}
+
+#[test]
+fn failed_mapping_replays_remaining_keys() {
+    let mut state = editor("\n");
+    state.map_keys =
+        Mapper::default().with_mapping(Mode::Insert, "jk", Message::ChangeMode(Mode::Normal));
+    feed(&mut state, "ijx");
+    assert_eq!(text_area(&state)[0], "jx");
+    assert!(matches!(state.mode, Mode::Insert));
+}
+
+#[test]
+fn short_mapping_fires_after_timeout() {
+    let mut state = editor("abc\n");
+    state.map_keys = Mapper::default()
+        .with_mapping(Mode::Normal, "g", Message::CursorEnd)
+        .with_mapping(Mode::Normal, "gh", Message::CursorHome);
+    feed(&mut state, "g");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+    timeout(&mut state);
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    feed(&mut state, "gh");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+}
+
+fn map(state: &mut State, cmd: &str, args: &str) {
+    let command = MapCommand::parse(cmd).expect("map command");
+    runtime::update(state, Message::Map(command, args.to_string()));
+}
+
+#[test]
+fn map_from_command_line_with_leader() {
+    let mut state = editor("abcd\n");
+    feed(&mut state, ":nmap <lt>leader>l ll<enter>");
+    feed(&mut state, "\\l");
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    feed(&mut state, ":set mapleader=,<enter>");
+    feed(&mut state, ":nmap <lt>leader>h 0<enter>");
+    feed(&mut state, ",h");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+}
+
+#[test]
+fn noremap_only_sees_default_mappings() {
+    let mut state = editor("abcd\n");
+    map(&mut state, "nmap", "Q l");
+    map(&mut state, "nmap", "W Q");
+    map(&mut state, "nnoremap", "E Q");
+    feed(&mut state, "W");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+    feed(&mut state, "E");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+    map(&mut state, "nnoremap", "l h");
+    feed(&mut state, "l");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+}
+
+#[test]
+fn recursive_mapping_stops() {
+    let mut state = editor("abcd\n");
+    map(&mut state, "nmap", "Q QQ");
+    feed(&mut state, "Q");
+    assert_eq!(state.map_depth, 0);
+    assert!(state.remap);
+    assert_eq!(state.messages.len(), 1);
+    feed(&mut state, "<esc>l");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+}
+
+#[test]
+fn unmap_and_buffer_local_mappings() {
+    let mut state = editor("abcd\n");
+    map(&mut state, "nmap", "Q $");
+    map(&mut state, "nmap", "<buffer> Q l");
+    feed(&mut state, "Q");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+    map(&mut state, "nunmap", "<buffer> Q");
+    feed(&mut state, "Q");
+    assert_eq!(screen(&state).cursor(), Pos::new(3, 0));
+    map(&mut state, "nunmap", "Q");
+    feed(&mut state, "0Q");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+    map(&mut state, "nunmap", "Q");
+    assert_eq!(state.messages.len(), 1);
+}
+
+#[test]
+fn which_key_popup_lists_continuations() {
+    let mut state = editor("abc\n");
+    state.map_keys = Mapper::default()
+        .with_mapping(Mode::Normal, "zh", Message::CursorHome)
+        .with_mapping(Mode::Normal, "zel", Message::CursorEnd);
+    state.add_mapping(Mode::Normal, "zx", "l", true, false, Some("right"));
+    feed(&mut state, "z");
+    assert!(text_area(&state)
+        .iter()
+        .all(|line| !line.contains("cursor")));
+    feed(&mut state, "<esc>:set whichkey<enter>z");
+    assert_eq!(
+        text_area(&state),
+        vec![
+            "abc",
+            "     e  +1 mappings",
+            "     h  cursor home",
+            "     x  right",
+        ]
+    );
+    feed(&mut state, "x");
+    assert_eq!(text_area(&state), vec!["abc", "", "", ""]);
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+}
+
+#[test]
+fn paste_in_insert_mode_skips_mappings() {
+    let mut state = editor("ab\n");
+    state.map_keys =
+        Mapper::default().with_mapping(Mode::Insert, "jk", Message::ChangeMode(Mode::Normal));
+    feed(&mut state, "i<right>");
+    runtime::update(&mut state, Message::Paste("jk\r\nxy".into()));
+    assert!(matches!(state.mode, Mode::Insert));
+    assert_eq!(text_area(&state), vec!["ajk", "xyb", "", ""]);
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 1));
+}
+
+#[test]
+fn paste_in_normal_mode_goes_after_cursor() {
+    let mut state = editor("ab\n");
+    runtime::update(&mut state, Message::Paste("XY".into()));
+    assert_eq!(text_area(&state)[0], "aXYb");
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    assert!(matches!(state.mode, Mode::Normal));
+}
+
+#[test]
+fn paste_on_command_line_is_one_line() {
+    let mut state = editor("\n");
+    feed(&mut state, ":");
+    runtime::update(&mut state, Message::Paste("set\nwhichkey".into()));
+    assert_eq!(
+        screen(&state).lines()[SIZE.height as usize - 1].trim_end(),
+        ":set whichkey"
+    );
+}
+
+#[test]
+fn paste_scrolls_to_keep_cursor_visible() {
+    let mut state = editor("\n");
+    feed(&mut state, "i");
+    runtime::update(&mut state, Message::Paste("1\n2\n3\n4\n5\n6".into()));
+    assert_eq!(text_area(&state), vec!["3", "4", "5", "6"]);
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 3));
+}
+
+fn mouse(state: &mut State, kind: MouseEventKind, x: u16, y: u16) {
+    let event = MouseEvent {
+        kind,
+        column: x,
+        row: y,
+        modifiers: KeyModifiers::NONE,
+    };
+    runtime::update(state, Message::Mouse(event));
+}
+
+#[test]
+fn click_moves_cursor_to_text() {
+    let mut state = editor("abc\nde\n\nlast\n");
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 1, 1);
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 1));
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 10, 0);
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 5, 3);
+    assert_eq!(screen(&state).cursor(), Pos::new(3, 3));
+    feed(&mut state, "i");
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 10, 1);
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 1));
+}
+
+#[test]
+fn drag_selects_in_visual_mode() {
+    let mut state = editor("abcd\nefgh\n");
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 1, 0);
+    mouse(&mut state, MouseEventKind::Drag(MouseButton::Left), 2, 1);
+    assert_eq!(state.mode, Mode::Visual);
+    assert_eq!(state.selection(), Some((1, 7)));
+    let backend = screen(&state);
+    assert_eq!(text_area(&state)[..2], ["abcd", "efgh"]);
+    let style = |x, y| backend.screen().get(x, y).unwrap().style.background_color;
+    assert_eq!(style(0, 0), None);
+    assert_eq!(style(1, 0), Some(Color::Grey));
+    assert_eq!(style(3, 0), Some(Color::Grey));
+    assert_eq!(style(2, 1), Some(Color::Grey));
+    assert_eq!(style(3, 1), None);
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 0, 0);
+    assert_eq!(state.mode, Mode::Normal);
+    assert_eq!(state.selection(), None);
+}
+
+#[test]
+fn wheel_scrolls_and_click_follows_scroll() {
+    let mut state = editor("1\n2\n3\n4\n5\n6\n7\n8\n");
+    mouse(&mut state, MouseEventKind::ScrollDown, 0, 0);
+    assert_eq!(text_area(&state), vec!["4", "5", "6", "7"]);
+    mouse(&mut state, MouseEventKind::Down(MouseButton::Left), 0, 1);
+    assert_eq!(state.buffers[state.focused].cursor_char(), 8);
+    mouse(&mut state, MouseEventKind::ScrollUp, 0, 0);
+    assert_eq!(text_area(&state)[0], "1");
+}
+
+#[test]
+fn click_on_status_bar_lists_buffers() {
+    let mut state = editor("abc\n");
+    mouse(
+        &mut state,
+        MouseEventKind::Down(MouseButton::Left),
+        0,
+        SIZE.height - 2,
+    );
+    assert_eq!(state.messages.len(), 1);
+}
+
+#[test]
+fn mouse_capture_follows_option() {
+    let mut state = editor("abc\n");
+    assert!(!state.mouse_capture());
+    feed(&mut state, ":set mouse<enter>");
+    assert!(state.mouse_capture());
+}
+
+/// Editor with `contents` written to a fresh file in the temp dir.
+fn editor_with_file(name: &str, contents: &str) -> (State, String) {
+    let dir = std::env::temp_dir().join(format!("revi-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let path = dir.join(name).to_string_lossy().to_string();
+    std::fs::write(&path, contents).expect("write test file");
+    let settings = Settings {
+        buffers: vec![Buffer::from_path(&path)],
+        recover: false,
+        state_file: None,
+        session: None,
+    };
+    let mut state = State::new(settings);
+    state.size = SIZE;
+    (state, path)
+}
+
+/// Answers the open prompt the way a key press would.
+fn answer(state: &mut State, key: char) {
+    runtime::update(state, Message::PromptKey(Keys::from(Key::from(key))));
+}
+
+/// Writes `contents` to `path` with a modification time that differs from the last one.
+fn change_file(path: &str, contents: &str) {
+    let old = std::fs::metadata(path).and_then(|m| m.modified()).unwrap();
+    std::fs::write(path, contents).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(old + std::time::Duration::from_secs(2))
+        .unwrap();
+}
+
+#[test]
+fn focus_gained_offers_to_reload_changed_file() {
+    let (mut state, path) = editor_with_file("reload.txt", "old\n");
+    change_file(&path, "new text\n");
+    runtime::update(&mut state, Message::CheckTime);
+    assert_eq!(state.prompts.len(), 1);
+    answer(&mut state, 'l');
+    assert!(state.prompts.is_empty());
+    assert_eq!(text_area(&state)[0], "new text");
+    runtime::update(&mut state, Message::CheckTime);
+    assert!(state.prompts.is_empty());
+}
+
+#[test]
+fn declined_reload_keeps_buffer_and_asks_once() {
+    let (mut state, path) = editor_with_file("keep.txt", "old\n");
+    feed(&mut state, "x");
+    change_file(&path, "new\n");
+    runtime::update(&mut state, Message::CheckTime);
+    answer(&mut state, 'n');
+    assert!(state.prompts.is_empty());
+    assert_eq!(text_area(&state)[0], "ld");
+    assert!(state.buffers[0].modified);
+    runtime::update(&mut state, Message::CheckTime);
+    assert!(state.prompts.is_empty());
+}
+
+#[test]
+fn touched_file_is_not_reported() {
+    let (mut state, path) = editor_with_file("touch.txt", "same\n");
+    change_file(&path, "same\n");
+    runtime::update(&mut state, Message::CheckTime);
+    assert!(state.prompts.is_empty());
+}
+
+#[test]
+fn autoread_reloads_unmodified_buffers() {
+    let (mut state, path) = editor_with_file("autoread.txt", "one\ntwo\n");
+    feed(&mut state, ":set autoread<enter>j");
+    change_file(&path, "three\n");
+    runtime::update(&mut state, Message::CheckTime);
+    assert!(state.prompts.is_empty());
+    assert_eq!(text_area(&state)[..2], ["three", ""]);
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+}
+
+#[test]
+fn watcher_reloads_clean_buffers_and_asks_about_modified_ones() {
+    let (mut state, path) = editor_with_file("watched.txt", "one\ntwo\n");
+    feed(&mut state, "j");
+    change_file(&path, "uno\ndos\ntres\n");
+    runtime::update(&mut state, Message::FilesChanged(vec![path.clone()]));
+    assert!(state.prompts.is_empty());
+    assert_eq!(text_area(&state)[..3], ["uno", "dos", "tres"]);
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 1));
+    feed(&mut state, "x");
+    change_file(&path, "changed again\n");
+    runtime::update(&mut state, Message::FilesChanged(vec![path]));
+    assert_eq!(state.prompts.len(), 1);
+    assert_eq!(state.buffers[0].get_all_text(), "uno\nos\ntres\n");
+}
+
+#[test]
+fn watcher_reloads_other_files_when_one_fails_to_load() {
+    let (_, first) = editor_with_file("broken.txt", "one\n");
+    let (_, second) = editor_with_file("fine.txt", "two\n");
+    let settings = Settings {
+        buffers: vec![Buffer::from_path(&first), Buffer::from_path(&second)],
+        recover: false,
+        state_file: None,
+        session: None,
+    };
+    let mut state = State::new(settings);
+    state.size = SIZE;
+    // not utf-8, the file reads but does not load
+    let old = std::fs::metadata(&first)
+        .and_then(|m| m.modified())
+        .unwrap();
+    std::fs::write(&first, [0xff, 0xfe, b'\n']).unwrap();
+    let file = std::fs::File::options().write(true).open(&first).unwrap();
+    file.set_modified(old + std::time::Duration::from_secs(2))
+        .unwrap();
+    change_file(&second, "dos\n");
+    runtime::update(&mut state, Message::FilesChanged(vec![first, second]));
+    assert_eq!(state.messages.len(), 1);
+    assert_eq!(state.buffers[0].get_all_text(), "one\n");
+    assert_eq!(state.buffers[1].get_all_text(), "dos\n");
+}
+
+#[test]
+fn failed_swap_write_is_reported_once_and_tried_again() {
+    let dir = std::env::temp_dir().join(format!("revi-test-{}", std::process::id()));
+    // the flattened path makes the swap file name too long to create
+    let path = dir.join(format!("{}.txt", "a".repeat(250)));
+    let settings = Settings {
+        buffers: vec![Buffer::from_path(&path.to_string_lossy())],
+        recover: false,
+        state_file: None,
+        session: None,
+    };
+    let mut state = State::new(settings);
+    state.size = SIZE;
+    feed(&mut state, "ix<esc>");
+    runtime::update(&mut state, Message::WriteSwapFiles);
+    assert_eq!(state.messages.len(), 1);
+    let attempts = state.swap_attempts;
+    runtime::update(&mut state, Message::WriteSwapFiles);
+    assert_eq!(state.messages.len(), 1);
+    assert_eq!(state.swap_attempts, attempts + 1);
+    assert!(state.buffers[0].unswapped_changes > 0);
+}
+
+#[test]
+fn tab_completes_option_names_with_wildmenu() {
+    let mut state = editor("\n");
+    feed(&mut state, ":set ma<tab>");
+    let lines = screen(&state).lines();
+    assert_eq!(lines[SIZE.height as usize - 1].trim_end(), ":set mapleader");
+    assert_eq!(
+        text_area(&state),
+        vec!["", "", "     mapleader", "     maplocalleader"]
+    );
+    let backend = screen(&state);
+    let bg = |y| backend.screen().get(5, y).unwrap().style.background_color;
+    assert_eq!(bg(2), Some(Color::White));
+    assert_eq!(bg(3), Some(Color::Grey));
+    feed(&mut state, "<tab>");
+    assert!(screen(&state).lines()[5].starts_with(":set maplocalleader"));
+    feed(&mut state, "<S-Tab><S-Tab>");
+    assert!(screen(&state).lines()[5].starts_with(":set ma "));
+    feed(&mut state, "x");
+    assert_eq!(text_area(&state), vec!["", "", "", ""]);
+}
+
+#[test]
+fn single_completion_is_taken_without_menu() {
+    let mut state = editor("\n");
+    feed(&mut state, ":set whi<tab>");
+    assert_eq!(text_area(&state), vec!["", "", "", ""]);
+    feed(&mut state, "<enter>");
+    assert!(state.options.whichkey);
+}
+
+#[test]
+fn fuzzy_completion_of_buffer_names() {
+    let (mut state, path) = editor_with_file("fuzzy-name.txt", "\n");
+    feed(&mut state, ":b fzn<tab>");
+    assert!(state.completion.is_none());
+    feed(&mut state, "<esc>:set wildfuzzy<enter>:b fzn<tab>");
+    assert_eq!(state.command.get_all_text(), format!("b {path}"));
+}
+
+fn command_line(state: &State) -> String {
+    screen(state).lines()[SIZE.height as usize - 1]
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn command_history_filters_by_prefix() {
+    let mut state = editor("\n");
+    feed(
+        &mut state,
+        ":set mouse<enter>:nmap Q l<enter>:set whichkey<enter>",
+    );
+    feed(&mut state, ":set<up>");
+    assert_eq!(command_line(&state), ":set whichkey");
+    feed(&mut state, "<up>");
+    assert_eq!(command_line(&state), ":set mouse");
+    feed(&mut state, "<up>");
+    assert_eq!(command_line(&state), ":set mouse");
+    feed(&mut state, "<down><down>");
+    assert_eq!(command_line(&state), ":set");
+    feed(&mut state, "<esc>:<up>");
+    assert_eq!(command_line(&state), ":set whichkey");
+}
+
+#[test]
+fn command_line_cursor_editing() {
+    let mut state = editor("\n");
+    feed(&mut state, ":et foo<left><left><left><left>");
+    assert_eq!(screen(&state).cursor(), Pos::new(3, SIZE.height - 1));
+    feed(&mut state, "<C-a>s<C-e> bar");
+    assert_eq!(command_line(&state), ":set foo bar");
+    feed(&mut state, "<C-w>");
+    assert_eq!(state.command.get_all_text(), "set foo ");
+    feed(&mut state, "<left><left><bs>");
+    assert_eq!(state.command.get_all_text(), "set fo ");
+    feed(&mut state, "<C-u>");
+    assert_eq!(state.command.get_all_text(), "o ");
+    feed(&mut state, "<bs><del>");
+    assert!(matches!(state.mode, Mode::Command));
+    feed(&mut state, "<end><bs><bs><bs>");
+    assert!(matches!(state.mode, Mode::Normal));
+}
+
+#[test]
+fn ctrl_r_inserts_registers_and_word() {
+    let mut state = editor("foo bar_baz\n");
+    feed(&mut state, "x:set mouse<enter>");
+    feed(&mut state, ":<C-r>:<C-r>\"<C-r>%");
+    assert_eq!(command_line(&state), format!(":set mousef{NO_NAME}"));
+    feed(&mut state, "<esc>llll:<C-r><C-w>");
+    assert_eq!(command_line(&state), ":bar_baz");
+}
+
+#[test]
+fn state_file_restores_history_registers_and_cursor() {
+    let (mut state, path) = editor_with_file("state.txt", "one\ntwo\nthree\n");
+    let state_path = std::path::PathBuf::from(format!("{path}.state"));
+    let _ = std::fs::remove_file(&state_path);
+    state.state_file = Some(state_path.clone());
+    feed(&mut state, "jjl:set mouse<enter>x");
+    runtime::update(&mut state, Message::Quit);
+    let settings = Settings {
+        buffers: vec![Buffer::from_path(&path)],
+        recover: false,
+        state_file: Some(state_path),
+        session: None,
+    };
+    let mut state = State::new(settings);
+    state.size = SIZE;
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 2));
+    assert_eq!(state.register('"').as_deref(), Some("h"));
+    feed(&mut state, ":<up>");
+    assert_eq!(command_line(&state), ":set mouse");
+}
+
+#[test]
+fn state_file_keeps_global_marks() {
+    let (mut state, path) = editor_with_file("marks.txt", "one\ntwo\nthree\n");
+    let state_path = std::path::PathBuf::from(format!("{path}.state"));
+    let _ = std::fs::remove_file(&state_path);
+    state.state_file = Some(state_path.clone());
+    feed(&mut state, "jjlmAma");
+    runtime::update(&mut state, Message::Quit);
+    let settings = Settings {
+        buffers: vec![Buffer::new_str(NO_NAME, "scratch\n")],
+        recover: false,
+        state_file: Some(state_path),
+        session: None,
+    };
+    let mut state = State::new(settings);
+    state.size = SIZE;
+    feed(&mut state, "`A");
+    assert_eq!(state.buffers.len(), 2);
+    assert_eq!(state.get_focused_buffer().name, path);
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 2));
+    feed(&mut state, "`a");
+    assert_eq!(state.messages.len(), 1);
+}
+
+#[test]
+fn session_restores_buffers_cursor_and_options() {
+    let (mut state, first) = editor_with_file("session_a.txt", "a\n");
+    let second = first.replace("session_a", "session_b");
+    std::fs::write(&second, "0\n1\n2\n3\n4\n5\n6\n7\n").expect("write test file");
+    let session = format!("{first}.session");
+    let _ = std::fs::remove_file(&session);
+    feed(
+        &mut state,
+        &format!(":e {second}<enter>jjjjjjl:set mouse<enter>"),
+    );
+    feed(&mut state, &format!(":mksession {session}<enter>"));
+    assert!(state.messages.is_empty());
+    feed(&mut state, &format!(":mksession {session}<enter>"));
+    assert_eq!(state.messages.len(), 1);
+    state.messages.clear();
+    feed(&mut state, &format!(":mksession! {session}<enter>"));
+    assert!(state.messages.is_empty());
+
+    let settings = Settings {
+        buffers: Vec::new(),
+        recover: false,
+        state_file: None,
+        session: Some(session.into()),
+    };
+    let state = State::new(settings);
+    let names = state
+        .buffers
+        .iter()
+        .map(|b| b.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![first.as_str(), second.as_str()]);
+    assert_eq!(state.focused, 1);
+    let cursor = state.get_focused_buffer().get_cursor();
+    assert_eq!(cursor.pos(), Pos::new(0, 6));
+    assert_eq!(cursor.scroll, Pos::new(0, 3));
+    assert!(state.options.mouse);
+}
+
+#[test]
+fn marks_move_with_edits() {
+    let mut state = editor("one\ntwo\nthree\nfour\n");
+    feed(&mut state, "jjlma");
+    feed(&mut state, "ggiXY<esc>");
+    feed(&mut state, "`a");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 2));
+    feed(&mut state, "gg'a");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 2));
+    feed(&mut state, "'b");
+    assert_eq!(state.messages.len(), 1);
+    feed(&mut state, ":marks<enter>:jumps<enter>");
+    assert_eq!(state.messages.len(), 3);
+}
+
+#[test]
+fn jumplist_goes_back_and_forth() {
+    let mut state = editor("one\ntwo\nthree\nfour\n");
+    feed(&mut state, "Ggg");
+    feed(&mut state, "<C-o>");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 3));
+    feed(&mut state, "<C-o>");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 3));
+    feed(&mut state, "<C-i>");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+    feed(&mut state, "``");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 3));
+}
+
+#[test]
+fn file_marks_and_jumps_cross_buffers() {
+    let (mut state, first) = editor_with_file("marks_a.txt", "a\nb\n");
+    let second = first.replace("marks_a", "marks_b");
+    std::fs::write(&second, "c\n").expect("write test file");
+    feed(&mut state, &format!("jmA:e {second}<enter>"));
+    assert_eq!(state.focused, 1);
+    feed(&mut state, "'A");
+    assert_eq!(state.focused, 0);
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 1));
+    feed(&mut state, "<C-o>");
+    assert_eq!(state.focused, 1);
+    feed(&mut state, "<C-i>");
+    assert_eq!(state.focused, 0);
+}
+
+#[test]
+fn change_list_walks_changes() {
+    let mut state = editor("one\ntwo\nthree\nfour\n");
+    feed(&mut state, "lxjjjx");
+    feed(&mut state, "gg");
+    feed(&mut state, "g;");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 3));
+    feed(&mut state, "g;");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+    feed(&mut state, "g,");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 3));
+}
+
+#[test]
+fn macros_record_and_replay_with_count() {
+    let mut state = editor("abcdef\n");
+    feed(&mut state, "qaxq");
+    assert_eq!(state.register('a').as_deref(), Some("x"));
+    assert_eq!(text_area(&state)[0], "bcdef");
+    feed(&mut state, "2@a");
+    assert_eq!(text_area(&state)[0], "def");
+    feed(&mut state, "@@");
+    assert_eq!(text_area(&state)[0], "ef");
+    feed(&mut state, "qAlq");
+    assert_eq!(state.register('a').as_deref(), Some("xl"));
+}
+
+#[test]
+fn macro_is_editable_text() {
+    let mut state = editor("abcdef\n");
+    feed(&mut state, "qb");
+    assert_eq!(command_line(&state), " recording @b");
+    feed(&mut state, "A!<esc>q");
+    assert_eq!(state.register('b').as_deref(), Some("A!<Esc>"));
+    feed(&mut state, ":let @b = 'A?<C-r>b'<enter>");
+    assert_eq!(state.register('b').as_deref(), Some("A?A!<Esc>"));
+    feed(&mut state, "@b");
+    assert_eq!(text_area(&state)[0], "abcdef!?A!");
+}
+
+#[test]
+fn dot_repeats_insert_session() {
+    let mut state = editor("a\nb\nc\n");
+    feed(&mut state, "A;<esc>j.j.");
+    assert_eq!(text_area(&state), vec!["a;", "b;", "c;", ""]);
+    feed(&mut state, "3.");
+    assert_eq!(text_area(&state)[2], "c;;;;");
+    feed(&mut state, "k2A-<esc>");
+    assert_eq!(text_area(&state)[1], "b;--");
+}
+
+#[test]
+fn dot_repeats_delete_with_count() {
+    let mut state = editor("abcdefghij\n");
+    feed(&mut state, "2x.");
+    assert_eq!(text_area(&state)[0], "efghij");
+    feed(&mut state, "3.");
+    assert_eq!(text_area(&state)[0], "hij");
+    assert_eq!(state.register('"').as_deref(), Some("efg"));
+}
+
+#[test]
+fn word_motions_take_counts() {
+    let mut state = editor("foo.bar baz qux\n\nend\n");
+    feed(&mut state, "2w");
+    assert_eq!(screen(&state).cursor(), Pos::new(4, 0));
+    feed(&mut state, "3w");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 1));
+    feed(&mut state, "2b");
+    assert_eq!(screen(&state).cursor(), Pos::new(8, 0));
+    feed(&mut state, "eW");
+    assert_eq!(screen(&state).cursor(), Pos::new(12, 0));
+    feed(&mut state, "}");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 1));
+    feed(&mut state, "``");
+    assert_eq!(screen(&state).cursor(), Pos::new(12, 0));
+    feed(&mut state, ":set iskeyword=@,.<enter>0w");
+    assert_eq!(screen(&state).cursor(), Pos::new(8, 0));
+}
+
+#[test]
+fn huge_count_before_line_end_stops_at_the_last_line() {
+    let mut state = editor("one\ntwo\nthree\n");
+    feed(&mut state, "j99999999999999999999$");
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 1));
+    feed(&mut state, "<esc>0l99999999999999999999d$");
+    assert_eq!(state.buffers[0].get_all_text(), "one\nt\n");
+}
+
+#[test]
+fn operators_work_on_motions() {
+    let mut state = editor("one two three\nfour five\n\nsix\n");
+    feed(&mut state, "dw");
+    assert_eq!(text_area(&state)[0], "two three");
+    assert_eq!(state.register('"').as_deref(), Some("one "));
+    feed(&mut state, "cwTWO<esc>");
+    assert_eq!(text_area(&state)[0], "TWO three");
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    feed(&mut state, "wd$");
+    assert_eq!(text_area(&state)[0], "TWO");
+    feed(&mut state, "j0yj");
+    assert_eq!(state.register('"').as_deref(), Some("four five\n\n"));
+    feed(&mut state, "d}");
+    assert_eq!(text_area(&state), vec!["TWO", "", "six", ""]);
+    feed(&mut state, "Gdk");
+    assert_eq!(text_area(&state), vec!["TWO", "", "", ""]);
+    // the last word of `d2w` may be on the next line
+    let mut state = editor(
+        "one two
+three four
+",
+    );
+    feed(&mut state, "wd2w");
+    assert_eq!(text_area(&state)[0], "one four");
+}
+
+#[test]
+fn dot_repeats_operator_with_motion() {
+    let mut state = editor("a b c d e f g\n");
+    feed(&mut state, "dw.");
+    assert_eq!(text_area(&state)[0], "c d e f g");
+    feed(&mut state, "d2w.");
+    assert_eq!(text_area(&state)[0], "g");
+    let mut state = editor("a b c d e f g\n");
+    feed(&mut state, "dw3.");
+    assert_eq!(text_area(&state)[0], "e f g");
+    feed(&mut state, "d<esc>.");
+    assert_eq!(text_area(&state)[0], "f g");
+}
+
+#[test]
+fn find_motions_repeat_and_take_counts() {
+    let mut state = editor("a-b-c-d-e\n");
+    feed(&mut state, "f-");
+    assert_eq!(screen(&state).cursor(), Pos::new(1, 0));
+    feed(&mut state, "2;");
+    assert_eq!(screen(&state).cursor(), Pos::new(5, 0));
+    feed(&mut state, ",");
+    assert_eq!(screen(&state).cursor(), Pos::new(3, 0));
+    feed(&mut state, "0t-;");
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    feed(&mut state, "Fa");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+    feed(&mut state, "fz");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 0));
+    feed(&mut state, "d2t-");
+    assert_eq!(text_area(&state)[0], "-c-d-e");
+    feed(&mut state, "ldf-.");
+    assert_eq!(text_area(&state)[0], "-e");
+    feed(&mut state, "dfz");
+    assert_eq!(text_area(&state)[0], "-e");
+    assert_eq!(state.mode, Mode::Normal);
+}
+
+#[test]
+fn percent_matches_brackets_across_lines() {
+    let mut state = editor("fn a() {\n    b[0];\n}\n");
+    feed(&mut state, "%");
+    assert_eq!(screen(&state).cursor(), Pos::new(5, 0));
+    feed(&mut state, "$%");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 2));
+    feed(&mut state, "%");
+    assert_eq!(screen(&state).cursor(), Pos::new(7, 0));
+    feed(&mut state, "``");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 2));
+    feed(&mut state, "kf[d%");
+    assert_eq!(text_area(&state)[1], "    b;");
+}
+
+#[test]
+fn text_objects_work_with_operators_and_visual_mode() {
+    let mut state = editor("call(one, two) \"x y\"\n");
+    feed(&mut state, "fnci(a<esc>");
+    assert_eq!(text_area(&state)[0], "call(a) \"x y\"");
+    feed(&mut state, "$di\"");
+    assert_eq!(text_area(&state)[0], "call(a) \"\"");
+    feed(&mut state, "0daw");
+    assert_eq!(text_area(&state)[0], "(a) \"\"");
+    let mut state = editor("one two three\n\nfour\n");
+    feed(&mut state, "wviwd");
+    assert_eq!(text_area(&state)[0], "one  three");
+    feed(&mut state, "yap");
+    assert_eq!(state.register('"').as_deref(), Some("one  three\n\n"));
+    feed(&mut state, "dip.");
+    assert_eq!(text_area(&state), vec!["four", "", "", ""]);
+}
+
+#[test]
+fn line_operators_take_counts_and_put_lines() {
+    let mut state = editor("one\ntwo\nthree\nfour\n");
+    feed(&mut state, "2dd");
+    assert_eq!(state.buffers[0].get_all_text(), "three\nfour\n");
+    assert_eq!(state.register('"').as_deref(), Some("one\ntwo\n"));
+    feed(&mut state, "p");
+    assert_eq!(state.buffers[0].get_all_text(), "three\none\ntwo\nfour\n");
+    assert_eq!(screen(&state).cursor(), Pos::new(0, 1));
+    feed(&mut state, "yyGP");
+    assert_eq!(
+        state.buffers[0].get_all_text(),
+        "three\none\ntwo\none\nfour\n"
+    );
+    feed(&mut state, "G2dd");
+    assert_eq!(state.buffers[0].get_all_text(), "three\none\ntwo\none\n");
+    feed(&mut state, "0yl3p");
+    assert_eq!(state.buffers[0].get_all_text(), "three\none\ntwo\noooone\n");
+    let buf = &state.buffers[0];
+    assert_eq!(buf.line_col(buf.cursor_char()), (3, 3));
+    feed(&mut state, "dy");
+    assert_eq!(state.buffers[0].get_all_text(), "three\none\ntwo\noooone\n");
+    assert_eq!(state.mode, Mode::Normal);
+}
+
+#[test]
+fn open_lines_keep_the_indent() {
+    let mut state = editor("fn main() {\n    one\n}\n");
+    feed(&mut state, "jotwo<esc>");
+    assert_eq!(
+        state.buffers[0].get_all_text(),
+        "fn main() {\n    one\n    two\n}\n"
+    );
+    feed(&mut state, "2Ozero<esc>");
+    assert_eq!(
+        state.buffers[0].get_all_text(),
+        "fn main() {\n    one\n    zero\n    zero\n    two\n}\n"
+    );
+    feed(&mut state, "Gox<esc>");
+    assert_eq!(
+        state.buffers[0].get_all_text(),
+        "fn main() {\n    one\n    zero\n    zero\n    two\n}\nx\n"
+    );
+}
+
+#[test]
+fn join_shift_and_change_lines() {
+    let mut state = editor("one\n    two\n)\nthree  \nfour\n");
+    feed(&mut state, "J");
+    assert_eq!(text_area(&state)[0], "one two");
+    assert_eq!(screen(&state).cursor(), Pos::new(3, 0));
+    feed(&mut state, "J");
+    assert_eq!(text_area(&state)[0], "one two)");
+    feed(&mut state, "j3J");
+    assert_eq!(text_area(&state)[1], "three  four");
+    let mut state = editor("one\ntwo\n\nthree\n");
+    feed(&mut state, "3>>");
+    assert_eq!(
+        state.buffers[0].get_all_text(),
+        "    one\n    two\n\nthree\n"
+    );
+    assert_eq!(screen(&state).cursor(), Pos::new(4, 0));
+    feed(&mut state, "j<lt><lt>");
+    assert_eq!(text_area(&state)[1], "two");
+    feed(&mut state, ">j");
+    assert_eq!(text_area(&state)[1], "    two");
+    assert_eq!(text_area(&state)[2], "");
+    feed(&mut state, "kccnew<esc>");
+    assert_eq!(text_area(&state)[0], "    new");
+    feed(&mut state, "jSold<esc>j.");
+    assert_eq!(
+        state.buffers[0].get_all_text(),
+        "    new\n    old\nold\nthree\n"
+    );
+}
+
+#[test]
+fn replace_mode_types_over_text_and_backspace_restores_it() {
+    let mut state = editor("one two\n");
+    feed(&mut state, "wRxyz!");
+    let backend = screen(&state);
+    assert!(backend.lines()[SIZE.height as usize - 2].starts_with("Replace"));
+    assert!(matches!(
+        backend.cursor_shape(),
+        Some(SetCursorStyle::SteadyUnderScore)
+    ));
+    assert_eq!(text_area(&state)[0], "one xyz!");
+    feed(&mut state, "<backspace><backspace>");
+    assert_eq!(text_area(&state)[0], "one xyo");
+    feed(&mut state, "<backspace><backspace><backspace>");
+    assert_eq!(text_area(&state)[0], "one two");
+    assert_eq!(screen(&state).cursor(), Pos::new(3, 0));
+    feed(&mut state, "<esc>02Rab<esc>");
+    assert_eq!(text_area(&state)[0], "ababtwo");
+    assert_eq!(screen(&state).cursor(), Pos::new(3, 0));
+    feed(&mut state, "l.");
+    assert_eq!(text_area(&state)[0], "abababab");
+}
+
+#[test]
+fn huge_count_before_insert_types_the_text_once() {
+    let mut state = editor("one\n");
+    feed(&mut state, "99999999999999999999ix<esc>");
+    assert_eq!(text_area(&state)[0], "xone");
+    feed(&mut state, "99999999999999999999Ry<esc>");
+    assert_eq!(text_area(&state)[0], "yone");
+}
+
+#[test]
+fn replace_char_takes_a_count() {
+    let mut state = editor("abcd\nef\n");
+    feed(&mut state, "3rx");
+    assert_eq!(text_area(&state)[0], "xxxd");
+    assert_eq!(screen(&state).cursor(), Pos::new(2, 0));
+    feed(&mut state, "j5ry");
+    assert_eq!(text_area(&state)[1], "ef");
+    feed(&mut state, "0gr-l.");
+    assert_eq!(text_area(&state)[1], "--");
+    feed(&mut state, "kr<esc>x");
+    assert_eq!(text_area(&state)[0], "xxd");
+    feed(&mut state, "r<enter>");
+    assert_eq!(state.buffers[0].get_all_text(), "x\nd\n--\n");
+}
@@ -0,0 +1,435 @@
+//! Text objects pick text around the cursor for operators and visual mode, like `iw` for the
+//! word under the cursor or `a(` for the brackets around it. The inner variants leave out the
+//! white space, quotes, brackets or tags that the around variants take along.
+use crate::motion::{Keyword, Target, Text};
+use ropey::Rope;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    /// `iw` and `aw`, `true` for `iW` and `aW`.
+    Word(bool),
+    /// `is` and `as`.
+    Sentence,
+    /// `ip` and `ap`, whole lines.
+    Paragraph,
+    /// `i"`, `i'` and `` i` `` with the quote char, on the cursor line.
+    Quote(char),
+    /// `i(`, `i[`, `i{` and `i<` with the open and close bracket.
+    Bracket(char, char),
+    /// `it` and `at`, a pair of XML or HTML tags.
+    Tag,
+}
+
+impl TextObject {
+    /// `count` objects at char index `idx`, `None` when there is none there.
+    pub fn target(
+        self,
+        rope: &Rope,
+        idx: usize,
+        count: usize,
+        inner: bool,
+        keyword: &Keyword,
+    ) -> Option<Target> {
+        let text = Text::new(rope, keyword);
+        let idx = idx.min(rope.len_chars());
+        let range = match self {
+            Self::Word(big) => word(&text, idx, count, big, inner)?,
+            Self::Sentence => sentence(&text, idx, count, inner)?,
+            Self::Paragraph => return paragraph(&text, idx, count, inner),
+            Self::Quote(quote) => self::quote(&text, idx, count, quote, inner)?,
+            Self::Bracket(open, close) => bracket(&text, idx, count, open, close, inner)?,
+            Self::Tag => tag(rope, idx, count, inner)?,
+        };
+        Some(Target {
+            range,
+            linewise: false,
+        })
+    }
+}
+
+type Range = std::ops::Range<usize>;
+
+/// First char of the line of `idx` and the index of its newline.
+fn line_bounds(text: &Text, idx: usize) -> (usize, usize) {
+    let line = text.line(idx);
+    let start = text.line_start(line);
+    (start, start + text.line_len(line))
+}
+
+fn is_blank(text: &Text, idx: usize) -> bool {
+    matches!(text.char(idx), Some(' ' | '\t'))
+}
+
+/// Words and the white space between them each count as one object for `iw`, `aw` takes a
+/// word with the white space after it, or before it at the end of a line.
+fn word(text: &Text, idx: usize, count: usize, big: bool, inner: bool) -> Option<Range> {
+    let (line_start, eol) = line_bounds(text, idx);
+    if idx >= eol {
+        return None;
+    }
+    let run_end = |i: usize| {
+        let class = text.class(i, big);
+        (i..eol)
+            .find(|j| text.class(*j, big) != class)
+            .unwrap_or(eol)
+    };
+    let class = text.class(idx, big);
+    let mut start = (line_start..idx)
+        .rev()
+        .find(|j| text.class(*j, big) != class)
+        .map_or(line_start, |j| j + 1);
+    let mut end = run_end(idx);
+    if inner {
+        for _ in 1..count {
+            if end < eol {
+                end = run_end(end);
+            }
+        }
+        return Some(start..end);
+    }
+    if class == 0 {
+        // white space with the word after it
+        for i in 0..count {
+            if i > 0 && end < eol && is_blank(text, end) {
+                end = run_end(end);
+            }
+            if end < eol {
+                end = run_end(end);
+            }
+        }
+        return Some(start..end);
+    }
+    let mut trailing = false;
+    for i in 0..count {
+        if i > 0 && end < eol {
+            end = run_end(end);
+        }
+        trailing = end < eol && is_blank(text, end);
+        if trailing {
+            end = run_end(end);
+        }
+    }
+    if !trailing {
+        let before = (line_start..start)
+            .rev()
+            .take_while(|j| is_blank(text, *j))
+            .count();
+        // indent stays
+        if before < start - line_start {
+            start -= before;
+        }
+    }
+    Some(start..end)
+}
+
+/// `is` stops before the white space after the sentence, `as` takes it along.
+fn sentence(text: &Text, idx: usize, count: usize, inner: bool) -> Option<Range> {
+    let len = text.len_chars();
+    if len == 0 {
+        return None;
+    }
+    let start = (0..=idx.min(len - 1))
+        .rev()
+        .find(|i| text.is_sentence_start(*i))
+        .unwrap_or(0);
+    let mut end = start;
+    for _ in 0..count {
+        end = (end + 1..len)
+            .find(|i| text.is_sentence_start(*i))
+            .unwrap_or(len);
+    }
+    if inner {
+        while end > start + 1 && text.char(end - 1).is_some_and(char::is_whitespace) {
+            end -= 1;
+        }
+    }
+    Some(start..end)
+}
+
+/// Paragraphs and the empty lines between them each count as one object for `ip`, `ap` takes
+/// a paragraph with the empty lines after it, or before it at the end of the text.
+fn paragraph(text: &Text, idx: usize, count: usize, inner: bool) -> Option<Target> {
+    let last_line = text.last_line();
+    let line = text.line(idx).min(last_line);
+    let run_end = |line: usize| {
+        let empty = text.is_empty_line(line);
+        (line..=last_line)
+            .find(|l| text.is_empty_line(*l) != empty)
+            .map_or(last_line, |l| l - 1)
+    };
+    let empty = text.is_empty_line(line);
+    let mut first = (0..line)
+        .rev()
+        .find(|l| text.is_empty_line(*l) != empty)
+        .map_or(0, |l| l + 1);
+    let mut last = run_end(line);
+    let runs = if inner { count } else { count * 2 };
+    let mut taken = 1;
+    while taken < runs && last < last_line {
+        last = run_end(last + 1);
+        taken += 1;
+    }
+    if !inner && taken < runs && !empty && first > 0 {
+        // the last paragraph takes the empty lines before it
+        first = (0..first)
+            .rev()
+            .find(|l| !text.is_empty_line(*l))
+            .map_or(0, |l| l + 1);
+    }
+    Some(text.lines(first, last))
+}
+
+/// Quotes on the cursor line pair up from its start, a quote after a backslash does not count.
+/// The pair around the cursor is used, or the first one after it.
+fn quote(text: &Text, idx: usize, count: usize, quote: char, inner: bool) -> Option<Range> {
+    let (line_start, eol) = line_bounds(text, idx);
+    let quotes = (line_start..eol)
+        .filter(|i| text.char(*i) == Some(quote))
+        .filter(|i| *i == line_start || text.char(i - 1) != Some('\\'))
+        .collect::<Vec<_>>();
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(open, close)| (*open..=*close).contains(&idx) || *open > idx)?;
+    // `2i"` takes the quotes along like vim
+    if inner && count < 2 {
+        return Some(open + 1..close);
+    }
+    if inner {
+        return Some(open..close + 1);
+    }
+    let (mut start, mut end) = (open, close + 1);
+    let after = (end..eol).take_while(|i| is_blank(text, *i)).count();
+    if after > 0 {
+        end += after;
+    } else {
+        let before = (line_start..start)
+            .rev()
+            .take_while(|i| is_blank(text, *i))
+            .count();
+        if before < start - line_start {
+            start -= before;
+        }
+    }
+    Some(start..end)
+}
+
+/// `count` levels of `open` and `close` around the cursor, across lines.
+fn bracket(
+    text: &Text,
+    idx: usize,
+    count: usize,
+    open: char,
+    close: char,
+    inner: bool,
+) -> Option<Range> {
+    let len = text.len_chars();
+    // on a closing bracket the pair is the one it closes
+    let mut from = match text.char(idx) {
+        Some(c) if c == close => idx.checked_sub(1)?,
+        _ => idx,
+    };
+    let (mut start, mut end) = (0usize, 0);
+    for level in 0..count {
+        if level > 0 {
+            from = start.checked_sub(1)?;
+        }
+        start = find_unmatched(text, (0..=from).rev(), open, close)?;
+        end = find_unmatched(text, start + 1..len, close, open)?;
+    }
+    if !inner {
+        return Some(start..end + 1);
+    }
+    // a block that starts and ends its own lines keeps the lines with the brackets
+    let mut inner_start = start + 1;
+    if text.char(inner_start) == Some('\n') {
+        inner_start += 1;
+    }
+    let end_line = text.line(end);
+    let mut inner_end = end;
+    if end_line > text.line(start) && text.first_non_blank(end_line) == end {
+        inner_end = text.line_start(end_line);
+    }
+    Some(inner_start.min(inner_end)..inner_end)
+}
+
+/// The first `wanted` bracket in `indices` that is not matched by an `other` one before it.
+fn find_unmatched(
+    text: &Text,
+    mut indices: impl Iterator<Item = usize>,
+    wanted: char,
+    other: char,
+) -> Option<usize> {
+    let mut depth = 0;
+    indices.find(|i| match text.char(*i) {
+        Some(c) if c == other => {
+            depth += 1;
+            false
+        }
+        Some(c) if c == wanted && depth > 0 => {
+            depth -= 1;
+            false
+        }
+        Some(c) => c == wanted,
+        None => false,
+    })
+}
+
+/// A tag from `<` to `>`, with its name and whether it closes.
+struct Tag {
+    start: usize,
+    end: usize,
+    name: String,
+    closing: bool,
+}
+
+/// Every opening and closing tag in the text, self closing tags, comments and declarations
+/// are left out.
+fn tags(rope: &Rope) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (i, c) in rope.chars().enumerate() {
+        match (c, &mut current) {
+            ('<', _) => current = Some((i, String::new())),
+            ('>', Some((start, body))) => {
+                let body = std::mem::take(body);
+                let start = *start;
+                current = None;
+                let closing = body.starts_with('/');
+                let body = body.trim_start_matches('/');
+                if body.starts_with(['!', '?']) || body.ends_with('/') {
+                    continue;
+                }
+                let name = body
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default();
+                if !name.is_empty() {
+                    tags.push(Tag {
+                        start,
+                        end: i,
+                        name: name.to_string(),
+                        closing,
+                    });
+                }
+            }
+            (c, Some((_, body))) => body.push(c),
+            _ => {}
+        }
+    }
+    tags
+}
+
+/// The `count`th pair of tags around the cursor, innermost first.
+fn tag(rope: &Rope, idx: usize, count: usize, inner: bool) -> Option<Range> {
+    let mut open: Vec<Tag> = Vec::new();
+    let mut pairs = Vec::new();
+    for tag in tags(rope) {
+        if !tag.closing {
+            open.push(tag);
+            continue;
+        }
+        // a closing tag closes the last open tag with its name, tags left open in between
+        // do not pair up
+        if let Some(at) = open.iter().rposition(|o| o.name == tag.name) {
+            let opening = open.drain(at..).next()?;
+            pairs.push((opening.start, opening.end, tag.start, tag.end));
+        }
+    }
+    pairs.retain(|(start, _, _, end)| (*start..=*end).contains(&idx));
+    pairs.sort_by_key(|(start, _, _, end)| end - start);
+    let (start, open_end, close_start, end) = *pairs.get(count - 1)?;
+    match inner {
+        true => Some(open_end + 1..close_start),
+        false => Some(start..end + 1),
+    }
+}
+
+#[cfg(test)]
+fn pick(src: &str, idx: usize, object: TextObject, count: usize, inner: bool) -> Option<String> {
+    let rope = Rope::from_str(src);
+    let keyword = Keyword::new("@,48-57,_,192-255");
+    let target = object.target(&rope, idx, count, inner, &keyword)?;
+    Some(rope.slice(target.range).to_string())
+}
+
+#[test]
+fn word_objects() {
+    let src = "  foo.bar  baz\n";
+    let word = TextObject::Word(false);
+    assert_eq!(pick(src, 3, word, 1, true).as_deref(), Some("foo"));
+    assert_eq!(pick(src, 3, word, 3, true).as_deref(), Some("foo.bar"));
+    assert_eq!(pick(src, 5, word, 1, false).as_deref(), Some("."));
+    assert_eq!(pick(src, 7, word, 1, false).as_deref(), Some("bar  "));
+    assert_eq!(pick(src, 12, word, 1, false).as_deref(), Some("  baz"));
+    assert_eq!(pick(src, 9, word, 1, false).as_deref(), Some("  baz"));
+    assert_eq!(
+        pick(src, 3, TextObject::Word(true), 1, false).as_deref(),
+        Some("foo.bar  ")
+    );
+}
+
+#[test]
+fn sentence_and_paragraph_objects() {
+    let src = "One two. Three four.\nFive.\n\nSix\n";
+    assert_eq!(
+        pick(src, 4, TextObject::Sentence, 1, true).as_deref(),
+        Some("One two.")
+    );
+    assert_eq!(
+        pick(src, 4, TextObject::Sentence, 1, false).as_deref(),
+        Some("One two. ")
+    );
+    assert_eq!(
+        pick(src, 10, TextObject::Sentence, 1, true).as_deref(),
+        Some("Three four.")
+    );
+    assert_eq!(
+        pick(src, 4, TextObject::Paragraph, 1, true).as_deref(),
+        Some("One two. Three four.\nFive.\n")
+    );
+    assert_eq!(
+        pick(src, 4, TextObject::Paragraph, 1, false).as_deref(),
+        Some("One two. Three four.\nFive.\n\n")
+    );
+    assert_eq!(
+        pick(src, 29, TextObject::Paragraph, 1, false).as_deref(),
+        Some("\nSix\n")
+    );
+}
+
+#[test]
+fn quote_bracket_and_tag_objects() {
+    let src = r#"say "a \"b\"" and "c" x"#;
+    let quote = TextObject::Quote('"');
+    assert_eq!(pick(src, 6, quote, 1, true).as_deref(), Some(r#"a \"b\""#));
+    assert_eq!(
+        pick(src, 0, quote, 1, false).as_deref(),
+        Some(r#""a \"b\"" "#)
+    );
+    assert_eq!(pick(src, 19, quote, 1, false).as_deref(), Some(r#""c" "#));
+    let src = "f(a, (b), [c]) {\n    x\n}\n";
+    let parens = TextObject::Bracket('(', ')');
+    assert_eq!(pick(src, 6, parens, 1, true).as_deref(), Some("b"));
+    assert_eq!(
+        pick(src, 6, parens, 2, false).as_deref(),
+        Some("(a, (b), [c])")
+    );
+    assert_eq!(pick(src, 7, parens, 1, false).as_deref(), Some("(b)"));
+    assert_eq!(pick(src, 3, TextObject::Bracket('[', ']'), 1, true), None);
+    let braces = TextObject::Bracket('{', '}');
+    assert_eq!(pick(src, 19, braces, 1, true).as_deref(), Some("    x\n"));
+    let src = "<div class=\"a\"><p>hi <br/>there</p></div>";
+    assert_eq!(
+        pick(src, 19, TextObject::Tag, 1, true).as_deref(),
+        Some("hi <br/>there")
+    );
+    assert_eq!(
+        pick(src, 19, TextObject::Tag, 2, true).as_deref(),
+        Some("<p>hi <br/>there</p>")
+    );
+    assert_eq!(
+        pick(src, 16, TextObject::Tag, 1, false).as_deref(),
+        Some("<p>hi <br/>there</p>")
+    );
+}
@@ -0,0 +1,82 @@
+//! Notices when other programs change the files of open buffers.
+//! There is no file notification crate among the dependencies, so files are polled for a new mtime.
+use crate::message::Message;
+use revi_ui::Sender;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// How often the files are looked at.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Subscription id for watching `paths`, a different set of files starts a new watcher.
+pub fn id(paths: &[String]) -> String {
+    format!("watch:{}", paths.join("\n"))
+}
+
+/// Sends `Message::FilesChanged` whenever some of `paths` got a new mtime, until the app stops watching them.
+pub fn poll(paths: Vec<String>, sender: Sender<Message>) {
+    let mut files = Mtimes::new(paths);
+    while sender.is_alive() {
+        std::thread::sleep(POLL_INTERVAL);
+        let changed = files.changed();
+        if !changed.is_empty() && !sender.send(Message::FilesChanged(changed)) {
+            break;
+        }
+    }
+}
+
+/// Last seen modification time of each file, `None` while the file does not exist.
+struct Mtimes {
+    seen: HashMap<String, Option<SystemTime>>,
+}
+
+impl Mtimes {
+    fn new(paths: Vec<String>) -> Self {
+        let seen = paths
+            .into_iter()
+            .map(|path| {
+                let mtime = mtime(&path);
+                (path, mtime)
+            })
+            .collect();
+        Self { seen }
+    }
+
+    /// Files whose mtime changed since the last call.
+    fn changed(&mut self) -> Vec<String> {
+        let mut changed = self
+            .seen
+            .iter_mut()
+            .filter_map(|(path, seen)| {
+                let mtime = mtime(path);
+                if mtime == *seen {
+                    return None;
+                }
+                *seen = mtime;
+                Some(path.clone())
+            })
+            .collect::<Vec<_>>();
+        changed.sort();
+        changed
+    }
+}
+
+fn mtime(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[test]
+fn mtimes_report_each_change_once() {
+    let dir = std::env::temp_dir().join(format!("revi-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("watched.txt").to_string_lossy().to_string();
+    std::fs::write(&path, "a").unwrap();
+    let mut files = Mtimes::new(vec![path.clone()]);
+    assert!(files.changed().is_empty());
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+    assert_eq!(files.changed(), vec![path.clone()]);
+    assert!(files.changed().is_empty());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(files.changed(), vec![path]);
+}